@@ -0,0 +1,82 @@
+use protocol::{Capability, FxrPayload, Operation, PatchFxrError, Reply, Request, Response};
+
+use crate::{agent_info, game, overrides, server};
+
+/// Performs a request from the host.
+pub(crate) fn handle(request: Request) -> Response {
+    Response {
+        id: request.id,
+        result: perform(request.operation),
+    }
+}
+
+fn perform(operation: Operation) -> Result<Reply, PatchFxrError> {
+    Ok(match operation {
+        Operation::Capabilities => Reply::Capabilities(game::capabilities()?),
+        Operation::Patch { fxrs, options } => {
+            let fxrs = unpack(fxrs)?;
+            let patcher = game::patcher()?;
+
+            // Persistent overrides are only ever redone from the SFX update hook
+            if options.persistent && !game::hookable(patcher) {
+                return Err(PatchFxrError::Unsupported(Capability::PersistentOverrides));
+            }
+
+            let outcomes = match options.dry_run {
+                true => fxrs.iter().map(|f| game::check_patch(patcher, f)).collect::<Result<_, _>>()?,
+                false => fxrs.into_iter().map(|f| game::patch(patcher, f, &options)).collect::<Result<_, _>>()?,
+            };
+
+            Reply::Patched(outcomes)
+        },
+        Operation::Insert { fxrs } => {
+            let fxrs = unpack(fxrs)?;
+            let patcher = game::patcher()?;
+            if !patcher.capabilities().contains(&Capability::Insert) {
+                return Err(PatchFxrError::Unsupported(Capability::Insert));
+            }
+
+            fxrs.into_iter().try_for_each(|f| game::insert(patcher, f))?;
+            Reply::Done
+        },
+        Operation::Restore { fxr_ids } => {
+            let patcher = game::patcher()?;
+
+            fxr_ids.into_iter().try_for_each(|id| game::restore(patcher, id))?;
+            Reply::Done
+        },
+        Operation::ListResident => {
+            let resident = game::patcher()?.resident_fxrs()?;
+
+            Reply::Resident(resident.into_iter().map(|f| f.id).collect())
+        },
+        Operation::Dump { fxr_id } => Reply::Dump(game::dump(game::patcher()?, fxr_id)?),
+        Operation::MemoryStats => Reply::MemoryStats(game::ALLOCATIONS.lock().unwrap().stats()),
+        Operation::ListOverrides => Reply::Overrides(overrides::OVERRIDES.lock().unwrap().entries()),
+        Operation::ClearOverrides { fxr_ids } => {
+            let mut overrides = overrides::OVERRIDES.lock().unwrap();
+
+            match fxr_ids {
+                Some(fxr_ids) => fxr_ids.into_iter().for_each(|id| { overrides.remove(id); }),
+                None => overrides.clear(),
+            }
+
+            Reply::Done
+        },
+        Operation::StartControlServer(options) => {
+            server::start(&options, agent_info())?;
+            Reply::Done
+        },
+    })
+}
+
+/// Takes the bytes out of the payloads, refusing any that don't match the
+/// hash they were sent with.
+fn unpack(fxrs: Vec<FxrPayload>) -> Result<Vec<Vec<u8>>, PatchFxrError> {
+    fxrs.into_iter()
+        .map(|f| match f.is_intact() {
+            true => Ok(f.bytes),
+            false => Err(PatchFxrError::CorruptPayload(f.describe())),
+        })
+        .collect()
+}
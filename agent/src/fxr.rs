@@ -1 +1,9 @@
+use ::fxr::Header;
+use protocol::PatchFxrError;
 
+/// Retrieves the FXR ID from the supplied FXR's header.
+pub(crate) fn read_fxr_id(fxr_bytes: &[u8]) -> Result<u32, PatchFxrError> {
+    Header::read(fxr_bytes)
+        .map(|header| header.id)
+        .map_err(|_| PatchFxrError::InvalidFxr)
+}
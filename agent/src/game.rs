@@ -1,20 +1,335 @@
-use protocol::PatchFxrError;
-use detection::RunningGame;
-use eldenring::EldenRingFxrPatcher;
-use armoredcore6::ArmoredCore6FxrPatcher;
+use std::sync;
+use std::time;
+use protocol::{
+    Capability, FxrDump, GameCapabilities, GameDetectionError, PatchFxrError, PatchOptions,
+    PatchOutcome, RunningGame,
+};
+use sfx::{
+    copy_definition, Allocation, AllocationTracker, Allocator, Memory, PendingSwap, ResidentFxr,
+    ResourceList, SwapKind, SwapQueue, SwapState,
+};
+use patcher::GameFxrPatcher;
+
+use crate::fxr::read_fxr_id;
+use crate::overrides::OVERRIDES;
 
 pub(crate) mod pattern;
 pub(crate) mod detection;
+pub(crate) mod hook;
+pub(crate) mod patcher;
 pub(crate) mod eldenring;
-pub(crate) mod armoredcore6;
 
-pub(crate) fn make_patcher(game: RunningGame) -> Result<Box<dyn FxrPatcher>, PatchFxrError> {
+/// Slots in the vtable of the game's FXR allocator, a `DLAllocator` like the
+/// rest of the engine's heaps.
+///
+/// `AllocateAligned(size, alignment)` at 0x50 is the slot the reloader has
+/// allocated FXRs from since it was first written, carried over from the
+/// original Elden Ring patcher. `Free(address)` at 0x68 follows from the
+/// `DLAllocator` vtable order: destructor, allocator ID, size and capacity
+/// queries, `Allocate` at 0x48, `AllocateAligned` at 0x50, the two
+/// reallocations and then `Free`. Only the 0x50 slot has been exercised in
+/// game, re-check 0x68 against the vtable `GET_ALLOCATOR_PATTERN` resolves to
+/// when the patterns are updated for a new game version.
+pub(crate) const ALLOCATE_ALIGNED: usize = 0x50;
+pub(crate) const FREE: usize = 0x68;
+
+/// How long the RPC call waits for the game to pick up a swap. Loading screens
+/// can stall the SFX update for a bit so this is fairly generous.
+const SWAP_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// The agent stays resident after the first call so the patcher, and the hook
+/// installed through it, outlive a single RPC call.
+static PATCHER: sync::OnceLock<Box<dyn FxrPatcher>> = sync::OnceLock::new();
+
+/// Swaps queued up by the RPC thread. They're drained from inside the SFX
+/// system's per-frame update so we never replace a definition while the
+/// effect threads are instancing it.
+static SWAPS: SwapQueue<PendingSwap> = SwapQueue::new();
+
+/// Every FXR definition the reloader has allocated inside of the game, keyed
+/// by FXR ID.
+pub(crate) static ALLOCATIONS: sync::Mutex<AllocationTracker> =
+    sync::Mutex::new(AllocationTracker::new());
+
+/// Retrieves the patcher for the running game, creating it on first use.
+pub(crate) fn patcher() -> Result<&'static dyn FxrPatcher, PatchFxrError> {
+    if let Some(patcher) = PATCHER.get() {
+        return Ok(patcher.as_ref());
+    }
+
+    let patcher = make_patcher(detection::detect_running_game()?)?;
+    Ok(PATCHER.get_or_init(|| patcher).as_ref())
+}
+
+fn make_patcher(game: RunningGame) -> Result<Box<dyn FxrPatcher>, PatchFxrError> {
+    // Armored Core 6 gets a profile, with its scene control at 0x90, once
+    // `RunningGame` can tell it apart
     Ok(match game {
-        RunningGame::EldenRing => Box::new(EldenRingFxrPatcher::new()?),
-        // RunningGame::ArmoredCore6 => Box::new(ArmoredCore6FxrPatcher::new()?),
+        RunningGame::EldenRing => Box::new(GameFxrPatcher::new(&eldenring::PROFILE)?),
     })
 }
 
-pub(crate) trait FxrPatcher {
-    fn patch(&self, fxr: Vec<u8>) -> Result<(), PatchFxrError>;
+/// Hooks the SFX update unless that already happened, and yields whether the
+/// hook is in place. The hook only goes in once something is changed in the
+/// game, so looking at a process or asking what the agent supports leaves the
+/// game untouched.
+fn ensure_hooked(patcher: &dyn FxrPatcher) -> Result<bool, PatchFxrError> {
+    if hook::frame_hook_installed() {
+        return Ok(true);
+    }
+
+    match patcher.sfx_update() {
+        Some(update) => hook::install_frame_hook(update).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Whether the SFX update is hooked or will be once something is changed in
+/// the game.
+pub(crate) fn hookable(patcher: &dyn FxrPatcher) -> bool {
+    hook::frame_hook_installed() || patcher.sfx_update().is_some()
+}
+
+/// Works out what the agent can do in the running game. Operations that need
+/// the patcher are unsupported if it couldn't be created, which is usually a
+/// game version whose patterns we don't match.
+pub(crate) fn capabilities() -> Result<GameCapabilities, GameDetectionError> {
+    let game = detection::detect_running_game()?;
+    let version = detection::detect_game_version()?;
+
+    let patcher = patcher().ok();
+    let supported = patcher.map_or(&[][..], |p| p.capabilities());
+    let capabilities = Capability::ALL.into_iter()
+        .filter(|c| match c {
+            // Persistent overrides are redone from the SFX update hook
+            Capability::PersistentOverrides => supported.contains(c) && patcher.is_some_and(hookable),
+            Capability::MemoryStats | Capability::ControlServer => true,
+            _ => supported.contains(c),
+        })
+        .collect();
+
+    Ok(GameCapabilities { game, version, capabilities })
+}
+
+/// Swaps in the supplied FXR if the game has its ID loaded. FXRs that aren't
+/// loaded are held on to until the game loads them. Persistent FXRs are held
+/// on to regardless so they can be swapped in again whenever the game reloads
+/// the FXR. Holding on to FXRs needs the SFX update hook, without it only
+/// loaded FXRs can be patched.
+///
+/// A one-off patch over a persistent override leaves the override in place,
+/// so the persistent FXR comes back the next time the game reloads the FXR.
+pub(crate) fn patch(
+    patcher: &dyn FxrPatcher,
+    fxr_bytes: Vec<u8>,
+    options: &PatchOptions,
+) -> Result<PatchOutcome, PatchFxrError> {
+    let fxr_id = read_fxr_id(&fxr_bytes)?;
+    let hooked = ensure_hooked(patcher)?;
+
+    if patcher.find_definition(fxr_id)?.is_none() {
+        // Nothing would ever pick the override up
+        if !hooked {
+            return Err(PatchFxrError::NotLoaded(fxr_id));
+        }
+
+        OVERRIDES.lock().unwrap().insert(fxr_id, fxr_bytes, options.persistent);
+        return Ok(PatchOutcome::Pending(fxr_id));
+    }
+
+    if options.persistent {
+        OVERRIDES.lock().unwrap().insert(fxr_id, fxr_bytes.clone(), true);
+    } else {
+        // A one-off FXR still waiting for this ID is outdated now
+        OVERRIDES.lock().unwrap().remove_one_off(fxr_id);
+    }
+
+    let allocation = patcher.prepare(&fxr_bytes)?;
+    commit_swap(patcher, PendingSwap { fxr_id, kind: SwapKind::Replace(allocation) })?;
+
+    Ok(PatchOutcome::Patched(fxr_id))
+}
+
+/// Goes through the same steps as `patch` without swapping anything in or
+/// holding on to the FXR, and reports what `patch` would do.
+pub(crate) fn check_patch(patcher: &dyn FxrPatcher, fxr_bytes: &[u8]) -> Result<PatchOutcome, PatchFxrError> {
+    // Checked the same way as `patch`, so a dry run doesn't refuse FXRs that
+    // would've gone through
+    let fxr_id = read_fxr_id(fxr_bytes)?;
+
+    Ok(match patcher.find_definition(fxr_id)? {
+        // Queued FXRs are only allocated once the game loads them
+        Some(_) if !patcher.allocator_available() => return Err(PatchFxrError::AllocatorUnavailable),
+        Some(_) => PatchOutcome::WouldPatch(fxr_id),
+        None if hookable(patcher) => PatchOutcome::WouldQueue(fxr_id),
+        None => return Err(PatchFxrError::NotLoaded(fxr_id)),
+    })
+}
+
+/// Adds an FXR under an ID that the game doesn't have loaded, which lets the
+/// FXR be used without repacking the game's archives. Inserted FXRs are
+/// tracked like patched ones, restoring one takes it back out of the list.
+pub(crate) fn insert(patcher: &dyn FxrPatcher, fxr_bytes: Vec<u8>) -> Result<(), PatchFxrError> {
+    let fxr_id = read_fxr_id(&fxr_bytes)?;
+
+    if patcher.find_definition(fxr_id)?.is_some() {
+        return Err(PatchFxrError::AlreadyLoaded(fxr_id));
+    }
+
+    ensure_hooked(patcher)?;
+    let allocation = patcher.prepare(&fxr_bytes)?;
+    commit_swap(patcher, PendingSwap { fxr_id, kind: SwapKind::Insert(allocation) })
+}
+
+/// Puts back the definition the game had loaded before we patched the FXR.
+/// Any override for the FXR is dropped as well.
+pub(crate) fn restore(patcher: &dyn FxrPatcher, fxr_id: u32) -> Result<(), PatchFxrError> {
+    let was_pending = OVERRIDES.lock().unwrap().remove(fxr_id);
+
+    if !ALLOCATIONS.lock().unwrap().is_tracked(fxr_id) {
+        return match was_pending {
+            true => Ok(()),
+            false => Err(PatchFxrError::NotPatched(fxr_id)),
+        };
+    }
+
+    ensure_hooked(patcher)?;
+    commit_swap(patcher, PendingSwap { fxr_id, kind: SwapKind::Restore })
+}
+
+/// Copies the definition the game currently has loaded for an FXR, whether
+/// that's the original or one we swapped in.
+pub(crate) fn dump(patcher: &dyn FxrPatcher, fxr_id: u32) -> Result<FxrDump, PatchFxrError> {
+    let memory = patcher.memory();
+    let definition = patcher.find_definition(fxr_id)?
+        .and_then(|slot| memory.read_pointer(slot))
+        .ok_or(PatchFxrError::NotLoaded(fxr_id))?;
+
+    Ok(FxrDump {
+        address: definition as u64,
+        bytes: copy_definition(memory, definition)?,
+    })
+}
+
+/// Applies the swaps queued up by the RPC thread. Only to be called from the
+/// SFX update hook.
+pub(crate) fn drain_swaps() {
+    if let Some(patcher) = PATCHER.get() {
+        // Anything retired last frame is no longer in use by now
+        release_retired(patcher.as_ref());
+        SWAPS.drain(|s| apply_swap(patcher.as_ref(), s));
+        apply_overrides(patcher.as_ref());
+    }
+}
+
+/// Swaps in the overrides for FXRs the game loaded since they were sent over
+/// and redoes persistent overrides that the game has since reloaded.
+///
+/// This walks the resource list every frame rather than hooking the function
+/// the game registers FXRs with. We don't have a pattern for that function in
+/// the game, while the SFX update is already hooked for the swaps. The
+/// walk is skipped entirely while there are no overrides and only reads the
+/// nodes otherwise, a few hundred of them on a busy map.
+fn apply_overrides(patcher: &dyn FxrPatcher) {
+    let mut overrides = OVERRIDES.lock().unwrap();
+    if overrides.is_empty() {
+        return;
+    }
+
+    let Ok(resident) = patcher.resident_fxrs() else {
+        return;
+    };
+
+    for fxr in resident {
+        // The game still has our definition, nothing to redo
+        let swapped_in = ALLOCATIONS.lock().unwrap().current(fxr.id) == Some(fxr.definition);
+        if swapped_in && overrides.is_persistent(fxr.id) {
+            continue;
+        }
+
+        // One-off overrides that can't be allocated are lost, persistent ones
+        // are retried next frame
+        if let Some(fxr_bytes) = overrides.due(fxr.id) {
+            if let Ok(allocation) = patcher.prepare(&fxr_bytes) {
+                apply_swap(patcher, PendingSwap { fxr_id: fxr.id, kind: SwapKind::Replace(allocation) });
+            }
+        }
+    }
+}
+
+/// Schedules a swap for the next frame and waits for it to go through. Falls
+/// back to swapping from the calling thread if we couldn't hook the SFX
+/// update. There are no overrides to apply then, `patch` refuses to hold on
+/// to FXRs without the hook.
+fn commit_swap(patcher: &dyn FxrPatcher, pending: PendingSwap) -> Result<(), PatchFxrError> {
+    let fxr_id = pending.fxr_id;
+
+    if !hook::frame_hook_installed() {
+        // Without the hook this is the closest we get to a safe point
+        release_retired(patcher);
+
+        return match apply_swap(patcher, pending) {
+            true => Ok(()),
+            false => Err(PatchFxrError::SwapFailed(fxr_id)),
+        };
+    }
+
+    match SWAPS.push(pending).wait(SWAP_TIMEOUT) {
+        SwapState::Applied => Ok(()),
+        SwapState::Failed => Err(PatchFxrError::SwapFailed(fxr_id)),
+        SwapState::Queued => Err(PatchFxrError::SwapTimeout(fxr_id)),
+    }
+}
+
+fn apply_swap(patcher: &dyn FxrPatcher, pending: PendingSwap) -> bool {
+    let mut allocations = ALLOCATIONS.lock().unwrap();
+
+    match patcher.resource_list() {
+        Ok(resources) => sfx::apply_swap(&resources, patcher, &mut allocations, pending),
+        Err(_) => {
+            pending.discard(&mut allocations);
+            false
+        },
+    }
+}
+
+fn release_retired(patcher: &dyn FxrPatcher) {
+    let retired = ALLOCATIONS.lock().unwrap().take_retired();
+    retired.into_iter().for_each(|a| patcher.free(a.address));
+}
+
+/// Patches FXRs in one of the games. The allocator is the one the game uses
+/// for its FXRs.
+pub(crate) trait FxrPatcher: Allocator + Send + Sync {
+    /// Operations the patcher supports for its game.
+    fn capabilities(&self) -> &'static [Capability];
+
+    /// Where the SFX system's per-frame update is, if its pattern matched.
+    /// It's hooked the first time something is changed in the game.
+    fn sfx_update(&self) -> Option<usize>;
+
+    /// Copies the FXR into memory from the game's FXR allocator and has the
+    /// game do its fix-ups on it.
+    fn prepare(&self, fxr: &[u8]) -> Result<Allocation, PatchFxrError>;
+
+    /// Whether the game's FXR allocator can be reached, without allocating
+    /// anything from it.
+    fn allocator_available(&self) -> bool;
+
+    /// Memory the game's structures are read from and written to.
+    fn memory(&self) -> &dyn Memory;
+
+    /// The game's list of loaded FXRs.
+    fn resource_list(&self) -> Result<ResourceList<'_, dyn Memory>, PatchFxrError>;
+
+    /// Locates the pointer to the definition in the wrapper of a loaded FXR.
+    /// Yields the address of the pointer, read and written through `memory`.
+    fn find_definition(&self, fxr_id: u32) -> Result<Option<usize>, PatchFxrError> {
+        self.resource_list()?.definition_slot(fxr_id)
+    }
+
+    /// Lists all FXRs in the game's resource list.
+    fn resident_fxrs(&self) -> Result<Vec<ResidentFxr>, PatchFxrError> {
+        self.resource_list()?.resident_fxrs()
+    }
 }
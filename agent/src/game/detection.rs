@@ -3,16 +3,11 @@ use std::ops::Range;
 use pelite::pe::Pe;
 use pelite::pe::PeView;
 use protocol::GameDetectionError;
+use protocol::RunningGame;
 
 pub const PRODUCT_NAME_ELDENRING: &str = "ELDEN RING™";
 // pub const PRODUCT_NAME_ARMOREDCORE6: &str = "ARMORED CORE™ VI FIRES OF RUBICON™";
 
-#[derive(Debug)]
-pub(crate) enum RunningGame {
-    EldenRing,
-    // ArmoredCore6,
-}
-
 /// Figures out what game we're currently running inside of.
 pub(crate) fn detect_running_game() -> Result<RunningGame, GameDetectionError> {
     let header = unsafe {
@@ -34,10 +29,32 @@ pub(crate) fn detect_running_game() -> Result<RunningGame, GameDetectionError> {
     })
 }
 
+/// Figures out what version of the game we're running inside of.
+pub(crate) fn detect_game_version() -> Result<String, GameDetectionError> {
+    let header = unsafe {
+        let handle = windows::Win32::System::LibraryLoader::GetModuleHandleA(std::ptr::null().into())
+            .map_err(|_| GameDetectionError::NoMainModuleHandle)?;
+
+        PeView::module(handle.0 as *const u8)
+    };
+
+    select_version_string(&header, "ProductVersion")?
+        .ok_or(GameDetectionError::MissingProductVersion)
+}
+
 /// Attempts to capture the product name from the PE header.
 fn select_product_name(
     header: &PeView,
 ) -> Result<String, GameDetectionError> {
+    select_version_string(header, "ProductName")?
+        .ok_or(GameDetectionError::MissingProductName)
+}
+
+/// Looks up one of the strings in the PE version info.
+fn select_version_string(
+    header: &PeView,
+    key: &str,
+) -> Result<Option<String>, GameDetectionError> {
     let resources = header.resources()
         .map_err(|_| GameDetectionError::MissingPEResources)?;
     let version_info = resources.version_info()
@@ -45,12 +62,12 @@ fn select_product_name(
     let language = version_info.translation().first()
         .ok_or(GameDetectionError::MissingPEStringsLanguage)?;
 
-    let mut product_name: Option<String> = None;
-    version_info.strings(*language, |k,v| if k == "ProductName" {
-        product_name = Some(v.to_string())
+    let mut value: Option<String> = None;
+    version_info.strings(*language, |k,v| if k == key {
+        value = Some(v.to_string())
     });
 
-    product_name.ok_or(GameDetectionError::MissingProductName)
+    Ok(value)
 }
 
 /// Attempts to find the .text section
@@ -0,0 +1,29 @@
+use retour::static_detour;
+use protocol::PatchFxrError;
+
+static_detour! {
+    static SFX_UPDATE: unsafe extern "system" fn(usize, usize);
+}
+
+/// Hooks the SFX system's per-frame update so that queued swaps are applied
+/// right before the game starts working with the FXR definitions for that
+/// frame.
+pub(crate) fn install_frame_hook(update: usize) -> Result<(), PatchFxrError> {
+    if SFX_UPDATE.is_enabled() {
+        return Ok(());
+    }
+
+    unsafe {
+        SFX_UPDATE
+            .initialize(std::mem::transmute(update), |sfx, task_data| {
+                super::drain_swaps();
+                SFX_UPDATE.call(sfx, task_data);
+            })
+            .and_then(|detour| detour.enable())
+            .map_err(|e| PatchFxrError::Hook(e.to_string()))
+    }
+}
+
+pub(crate) fn frame_hook_installed() -> bool {
+    SFX_UPDATE.is_enabled()
+}
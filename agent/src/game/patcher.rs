@@ -0,0 +1,192 @@
+use pattern::{
+    match_instruction_pattern, match_unique_instruction_pattern, GET_ALLOCATOR_PATTERN,
+    PATCH_OFFSETS_PATTERN, SFX_UPDATE_PATTERN, WTF_FXR_PATTERN,
+};
+
+use protocol::{Capability, PatchFxrError};
+use sfx::{Allocation, Allocator, Memory, ResourceLayout, ResourceList};
+
+use crate::{
+    game::FxrPatcher,
+    memory::GameMemory,
+    singleton::{self, DLRFLocatable},
+};
+
+use super::{pattern, ALLOCATE_ALIGNED, FREE};
+
+type FxrAllocatorGetter = unsafe extern "system" fn() -> usize;
+type AllocateFxr = unsafe extern "system" fn(usize, usize, usize) -> usize;
+type FreeFxr = unsafe extern "system" fn(usize, usize);
+type PatchFxrOffsets = unsafe extern "system" fn(usize, usize, usize) -> *const std::ffi::c_void;
+type PrepareFxr = unsafe extern "system" fn(usize) -> *const std::ffi::c_void;
+
+/// What sets one game apart from the others. The functions the patcher calls
+/// are found through the same patterns in every game.
+#[derive(Debug)]
+pub(crate) struct GameProfile {
+    pub capabilities: &'static [Capability],
+    pub resource_layout: ResourceLayout,
+    /// Whether the SFX update pattern was checked against the game. Without it
+    /// swaps happen from the RPC thread.
+    pub hook_sfx_update: bool,
+}
+
+/// Patches FXRs in any of the games, going by its profile.
+#[derive(Debug)]
+pub struct GameFxrPatcher {
+    profile: &'static GameProfile,
+    patch_fxr_offset: PatchFxrOffsets,
+    prepare_fxr: PrepareFxr,
+    fxr_allocator_getter: FxrAllocatorGetter,
+    sfx_update: Option<usize>,
+}
+
+impl GameFxrPatcher {
+    pub fn new(profile: &'static GameProfile) -> Result<Self, PatchFxrError> {
+        let get_allocator =
+            {
+                let matched = match_instruction_pattern(GET_ALLOCATOR_PATTERN).ok_or(
+                    PatchFxrError::InstructionPattern("get_allocator_call".to_string()),
+                )?;
+
+                let capture = matched.captures.first().unwrap();
+                let offset =
+                    i32::from_le_bytes(capture.bytes.as_slice().try_into().map_err(|_| {
+                        PatchFxrError::InstructionPattern("get_allocator".to_string())
+                    })?);
+
+                let rip = capture.location + 4;
+
+                // End me
+                if offset.is_positive() {
+                    rip + offset as usize
+                } else {
+                    rip - offset.unsigned_abs() as usize
+                }
+            } as usize;
+
+        unsafe {
+            Ok(Self {
+                profile,
+                patch_fxr_offset: std::mem::transmute(
+                    match_instruction_pattern(PATCH_OFFSETS_PATTERN)
+                        .ok_or(PatchFxrError::InstructionPattern("patch_fxr".to_string()))?
+                        .location,
+                ),
+                prepare_fxr: std::mem::transmute(
+                    match_instruction_pattern(WTF_FXR_PATTERN)
+                        .ok_or(PatchFxrError::InstructionPattern("wtf_fxr".to_string()))?
+                        .location,
+                ),
+                fxr_allocator_getter: std::mem::transmute(get_allocator),
+                // Not finding the SFX update isn't fatal, we'll just swap from
+                // the RPC thread like we used to.
+                sfx_update: profile.hook_sfx_update
+                    .then(|| match_unique_instruction_pattern(SFX_UPDATE_PATTERN))
+                    .flatten()
+                    .map(|m| m.location),
+            })
+        }
+    }
+}
+
+/// The same allocator the game uses for its FXRs.
+impl Allocator for GameFxrPatcher {
+    fn allocate(&self, size: usize, alignment: usize) -> usize {
+        let allocator = unsafe { (self.fxr_allocator_getter)() };
+
+        let allocate: AllocateFxr = unsafe {
+            std::mem::transmute(
+                *((*(allocator as *const usize) + ALLOCATE_ALIGNED) as *const usize)
+            )
+        };
+
+        unsafe { allocate(allocator, size, alignment) }
+    }
+
+    fn free(&self, address: usize) {
+        let allocator = unsafe { (self.fxr_allocator_getter)() };
+
+        let free: FreeFxr = unsafe {
+            std::mem::transmute(
+                *((*(allocator as *const usize) + FREE) as *const usize)
+            )
+        };
+
+        unsafe { free(allocator, address) };
+    }
+}
+
+impl FxrPatcher for GameFxrPatcher {
+    fn capabilities(&self) -> &'static [Capability] {
+        self.profile.capabilities
+    }
+
+    fn sfx_update(&self) -> Option<usize> {
+        self.sfx_update
+    }
+
+    fn prepare(&self, fxr_bytes: &[u8]) -> Result<Allocation, PatchFxrError> {
+        let allocation = self.allocate(fxr_bytes.len(), 0x10);
+        if allocation == 0 {
+            return Err(PatchFxrError::AllocatorUnavailable);
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                fxr_bytes.as_ptr(),
+                allocation as *mut u8,
+                fxr_bytes.len(),
+            );
+        }
+
+        unsafe {
+            (self.patch_fxr_offset)(allocation, allocation, allocation);
+            (self.prepare_fxr)(allocation);
+        }
+
+        Ok(Allocation {
+            address: allocation,
+            size: fxr_bytes.len(),
+        })
+    }
+
+    fn allocator_available(&self) -> bool {
+        let allocator = unsafe { (self.fxr_allocator_getter)() };
+        if allocator == 0 {
+            return false;
+        }
+
+        // Both the allocate and free entries of its vtable have to be there
+        let vtable = unsafe { *(allocator as *const usize) };
+        vtable != 0 && unsafe {
+            *((vtable + ALLOCATE_ALIGNED) as *const usize) != 0 && *((vtable + FREE) as *const usize) != 0
+        }
+    }
+
+    fn memory(&self) -> &dyn Memory {
+        &GameMemory
+    }
+
+    fn resource_list(&self) -> Result<ResourceList<'_, dyn Memory>, PatchFxrError> {
+        ResourceList::locate(&GameMemory, CSSfx::instance()?, &self.profile.resource_layout)
+    }
+}
+
+/// Only here to look up the instance, everything past it is read through
+/// `ResourceList`.
+struct CSSfx;
+
+impl CSSfx {
+    fn instance() -> Result<usize, PatchFxrError> {
+        singleton::get_instance::<Self>()?
+            .map(|instance| instance as usize)
+            .ok_or(PatchFxrError::CSSfxInstanceMissing)
+    }
+}
+
+impl DLRFLocatable for CSSfx {
+    fn name() -> &'static str {
+        "CSSfx"
+    }
+}
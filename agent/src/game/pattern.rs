@@ -1,19 +1,48 @@
+use std::ops::Range;
+
 use broadsword::{runtime, scanner};
 
 /// Takes an instruction pattern and looks for its location
 pub(crate) fn match_instruction_pattern(pattern: &str) -> Option<PatternResult> {
+    scan_text(pattern, 0)
+}
+
+/// Like `match_instruction_pattern`, but only yields the match if nothing
+/// else in .text matches. Used for the functions we hook, where settling for
+/// the first of several matches could put the detour on some unrelated
+/// function.
+pub(crate) fn match_unique_instruction_pattern(pattern: &str) -> Option<PatternResult> {
+    let matched = scan_text(pattern, 0)?;
+    let text_start = text_section().start;
+
+    match scan_text(pattern, matched.location - text_start + 1) {
+        Some(_) => None,
+        None => Some(matched),
+    }
+}
+
+fn text_section() -> Range<usize> {
     // Find .text section details since that's where the code lives
-    let text_section = runtime::get_module_section_range("eldenring.exe", ".text")
+    runtime::get_module_section_range("eldenring.exe", ".text")
         .or_else(|_| runtime::get_module_section_range("armoredcore6.exe", ".text"))
         .or_else(|_| runtime::get_module_section_range("sekiro.exe", ".text"))
         .or_else(|_| runtime::get_module_section_range("start_protected_game.exe", ".text"))
-        .unwrap();
+        .unwrap()
+}
+
+/// Scans .text for the pattern, skipping the first `skip` bytes.
+fn scan_text(pattern: &str, skip: usize) -> Option<PatternResult> {
+    let text_section = text_section();
+    let start = text_section.start + skip;
+    if start >= text_section.end {
+        return None;
+    }
 
     // Represent search area as a slice
     let scan_slice = unsafe {
         std::slice::from_raw_parts(
-            text_section.start as *const u8,
-            text_section.end - text_section.start,
+            start as *const u8,
+            text_section.end - start,
         )
     };
 
@@ -22,11 +51,11 @@ pub(crate) fn match_instruction_pattern(pattern: &str) -> Option<PatternResult>
     scanner::simple::scan(scan_slice, &pattern)
         // TODO: this kinda of rebasing can be done in broadsword probably
         .map(|result| PatternResult {
-            location: text_section.start + result.location,
+            location: start + result.location,
             captures: result.captures.into_iter()
                 .map(|capture| {
                     PatternCapture {
-                        location: text_section.start + capture.location,
+                        location: start + capture.location,
                         bytes: capture.bytes,
                     }
                 })
@@ -110,6 +139,31 @@ pub(crate) const WTF_FXR_PATTERN: &str = concat!(
     "01001... 10001011 10000100 ..100100 01000000 00000001 00000000 00000000",
 );
 
+// CSSfx's per-frame update task in Elden Ring. Runs on the main thread before
+// the effect jobs for the frame are kicked off, which makes it our safe point
+// for swapping definitions. Its prologue is common, what pins it down is the
+// load of GXFfxSceneCtrl from CSSfx + 0x60 (the `scene_ctrl` of the ER
+// resource layout) with RBX still holding CSSfx, and the pattern has to match
+// exactly once before we hook it. Not checked against Armored Core 6.
+//           48 89 5c        MOV        qword ptr [RSP + 0x8],RBX
+//           24 08
+//           57              PUSH       RDI
+//           48 83 ec 20     SUB        RSP,0x20
+//           48 8b fa        MOV        RDI,RDX
+//           48 8b d9        MOV        RBX,RCX
+//           e8 .. .. ..     CALL       CSSfx::UpdateTime
+//           ..
+//           48 8b 4b 60     MOV        RCX,qword ptr [RBX + 0x60]
+pub(crate) const SFX_UPDATE_PATTERN: &str = concat!(
+    "01001... 10001001 01011100 ..100100 00001000",
+    "01010111",
+    "01001... 10000011 11101100 00100000",
+    "01001... 10001011 11111010",
+    "01001... 10001011 11011001",
+    "11101000 ........ ........ ........ ........",
+    "01001... 10001011 01001011 01100000",
+);
+
 // 1420fbda7 48 8b 44        MOV        RAX,qword ptr [RSP + 0x28]=>local_50
 //           24 28
 // 1420fbdac 8b 40 04        MOV        EAX,dword ptr [RAX + 0x4]
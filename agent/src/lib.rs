@@ -1,14 +1,31 @@
-use protocol::PatchFxrError;
+use protocol::{AgentInfo, Request, Response, PROTOCOL_VERSION};
 
+mod call;
 mod game;
 mod fxr;
+mod memory;
 mod singleton;
+mod overrides;
+mod server;
 
 dll_syringe::payload_procedure! {
-    fn PatchFxr(fxrs: Vec<Vec<u8>>) -> Result<(), PatchFxrError> {
-        let game = game::detection::detect_running_game()?;
-        let patcher = game::make_patcher(game)?;
+    fn Handshake() -> AgentInfo {
+        agent_info()
+    }
+}
+
+dll_syringe::payload_procedure! {
+    fn Call(request: Request) -> Response {
+        call::handle(request)
+    }
+}
 
-        fxrs.into_iter().try_for_each(|f| patcher.patch(f))
+/// What the agent tells hosts about itself, over RPC as well as over the
+/// control channels.
+pub(crate) fn agent_info() -> AgentInfo {
+    AgentInfo {
+        protocol_version: PROTOCOL_VERSION,
+        hooked: game::hook::frame_hook_installed(),
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
     }
 }
@@ -0,0 +1,32 @@
+use sfx::Memory;
+
+/// The memory of the process the agent was injected into.
+#[derive(Debug)]
+pub(crate) struct GameMemory;
+
+impl Memory for GameMemory {
+    fn read(&self, address: usize, buffer: &mut [u8]) -> bool {
+        if address == 0 {
+            return false;
+        }
+
+        // Everything we read is reached from the game's own pointers
+        unsafe {
+            std::ptr::copy_nonoverlapping(address as *const u8, buffer.as_mut_ptr(), buffer.len());
+        }
+
+        true
+    }
+
+    fn write(&self, address: usize, bytes: &[u8]) -> bool {
+        if address == 0 {
+            return false;
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+        }
+
+        true
+    }
+}
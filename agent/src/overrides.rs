@@ -0,0 +1,79 @@
+use std::sync;
+use std::collections;
+use protocol::OverrideEntry;
+
+/// FXRs that have to be swapped in once the game loads their ID.
+pub(crate) static OVERRIDES: sync::Mutex<OverrideRegistry> =
+    sync::Mutex::new(OverrideRegistry::new());
+
+#[derive(Debug)]
+struct Override {
+    fxr: Vec<u8>,
+    /// Persistent overrides stick around after being swapped in so they can
+    /// be swapped in again when the game reloads the FXR.
+    persistent: bool,
+}
+
+/// Holds on to FXRs until the game loads their ID, at which point they're
+/// taken out and swapped in. Only the latest FXR sent for an ID is kept.
+#[derive(Debug)]
+pub(crate) struct OverrideRegistry {
+    overrides: collections::BTreeMap<u32, Override>,
+}
+
+impl OverrideRegistry {
+    pub const fn new() -> Self {
+        Self {
+            overrides: collections::BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    pub fn insert(&mut self, fxr_id: u32, fxr: Vec<u8>, persistent: bool) {
+        self.overrides.insert(fxr_id, Override { fxr, persistent });
+    }
+
+    /// Drops the override for an ID. Returns whether there was one.
+    pub fn remove(&mut self, fxr_id: u32) -> bool {
+        self.overrides.remove(&fxr_id).is_some()
+    }
+
+    /// Drops the override for an ID unless it's persistent. Returns whether
+    /// there was one to drop.
+    pub fn remove_one_off(&mut self, fxr_id: u32) -> bool {
+        match self.is_persistent(fxr_id) {
+            true => false,
+            false => self.remove(fxr_id),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.overrides.clear();
+    }
+
+    pub fn is_persistent(&self, fxr_id: u32) -> bool {
+        self.overrides.get(&fxr_id).is_some_and(|o| o.persistent)
+    }
+
+    /// Yields the FXR to swap in for an ID that the game just loaded. One-off
+    /// overrides are taken out of the registry, persistent ones remain.
+    pub fn due(&mut self, fxr_id: u32) -> Option<Vec<u8>> {
+        match self.overrides.get(&fxr_id)?.persistent {
+            true => self.overrides.get(&fxr_id).map(|o| o.fxr.clone()),
+            false => self.overrides.remove(&fxr_id).map(|o| o.fxr),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<OverrideEntry> {
+        self.overrides.iter()
+            .map(|(fxr_id, o)| OverrideEntry {
+                fxr_id: *fxr_id,
+                persistent: o.persistent,
+                size: o.fxr.len(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::sync::Mutex;
+use std::thread;
+
+use protocol::{AgentInfo, ControlServerOptions, PatchFxrError};
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{GetLastError, ERROR_PIPE_CONNECTED};
+use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+use windows::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+    PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+};
+
+use crate::call;
+
+/// Size of the pipe's buffers in either direction.
+const PIPE_BUFFER_SIZE: u32 = 0x10000;
+
+/// Control channels that are currently open.
+struct Listeners {
+    pipe: bool,
+    tcp_ports: Vec<u16>,
+}
+
+static LISTENERS: Mutex<Listeners> = Mutex::new(Listeners { pipe: false, tcp_ports: Vec::new() });
+
+/// Opens the control channels that aren't open yet. Every connection gets
+/// its own thread, requests are handled exactly like the ones coming in
+/// through the injected RPC.
+pub(crate) fn start(options: &ControlServerOptions, agent: AgentInfo) -> Result<(), PatchFxrError> {
+    let mut listeners = LISTENERS.lock().unwrap();
+
+    if let Some(port) = options.tcp_port.filter(|p| !listeners.tcp_ports.contains(p)) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|e| PatchFxrError::ControlServer(e.to_string()))?;
+
+        let agent = agent.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let agent = agent.clone();
+                thread::spawn(move || protocol::serve_connection(stream, &agent, call::handle));
+            }
+        });
+
+        listeners.tcp_ports.push(port);
+    }
+
+    if options.pipe && !listeners.pipe {
+        let name = protocol::pipe_name(std::process::id());
+        thread::spawn(move || {
+            // A new instance is created for every client so the next one can
+            // connect while this one is being served
+            while let Ok(pipe) = accept_pipe(&name) {
+                let agent = agent.clone();
+                thread::spawn(move || protocol::serve_connection(pipe, &agent, call::handle));
+            }
+
+            // Let the next request open the pipe again
+            LISTENERS.lock().unwrap().pipe = false;
+        });
+
+        listeners.pipe = true;
+    }
+
+    Ok(())
+}
+
+/// Creates an instance of the named pipe and waits for a client to connect
+/// to it. Only clients on this machine can connect, the pipe would otherwise
+/// be reachable over SMB.
+fn accept_pipe(name: &str) -> io::Result<File> {
+    let handle = unsafe {
+        CreateNamedPipeW(
+            &HSTRING::from(name),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            PIPE_BUFFER_SIZE,
+            PIPE_BUFFER_SIZE,
+            0,
+            None,
+        )
+    };
+
+    if handle.is_invalid() {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The pipe owns the handle from here on, closing it when dropped
+    let pipe = unsafe { File::from_raw_handle(handle.0 as RawHandle) };
+
+    // A client connecting between creating and connecting the pipe is
+    // reported as an error but is exactly what we're waiting for
+    let connected = unsafe { ConnectNamedPipe(handle, None) }.as_bool();
+    if !connected && unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(pipe)
+}
@@ -4,7 +4,6 @@ use std::sync;
 use std::slice;
 use std::collections;
 use broadsword::runtime;
-use broadsword::scanner;
 use protocol::LookupError;
 use protocol::SectionLookupError;
 use protocol::SingletonMapError;
@@ -43,19 +42,6 @@ pub fn get_instance<T: DLRFLocatable>() -> Result<Option<*mut T>, LookupError> {
     }
 }
 
-const NULL_CHECK_PATTERN: &str = concat!(
-    //  0 MOV REG, [MEM]
-    "01001... 10001011 00...101 [........ ........ ........ ........]",
-    //  7 TEST REG, REG
-    "01001... 10000101 11......",
-    // 10 JNZ +2e
-    "01110101 ........",
-    // 12 LEA RCX, [runtime_class_metadata]
-    "01001... 10001101 00001101 [........ ........ ........ ........]",
-    // 19 CALL get_singleton_name
-    "11101000 [........ ........ ........ ........]",
-);
-
 /// Builds a table of all the singletons. It does so by looking for null checks
 /// in the game by using an instance pattern. It then cycles over all 
 /// candidates and vets the involved pointers. We expect a pointer to the 
@@ -69,59 +55,24 @@ fn build_singleton_table() -> Result<SingletonMap, SingletonMapError> {
     let (data_range, _) = get_section(".data")
         .map_err(|e| SingletonMapError::Section(".data".to_string(), e))?;
 
-    let pattern = scanner::Pattern::from_bit_pattern(NULL_CHECK_PATTERN)
+    let candidates = singletons::find_singletons(text_slice, text_range.start, &data_range)
         .map_err(|_| SingletonMapError::Pattern)?;
 
     let mut results: SingletonMap = Default::default();
-    for candidate in scanner::simple::scan_all(text_slice, &pattern) {
-        let static_offset = u32::from_le_bytes(
-            candidate.captures[0].bytes.as_slice()
-                .try_into().unwrap()
-        );
-
-        let metadata_offset = u32::from_le_bytes(
-            candidate.captures[1].bytes.as_slice()
-                .try_into().unwrap()
-        );
-
-        let fn_offset = u32::from_le_bytes(
-            candidate.captures[2].bytes.as_slice()
-                .try_into().unwrap()
-        );
-
-        let candidate_base = text_range.start + candidate.location;
-
-        // Pointer to the instance of the singleton'd class
-        let static_address = candidate_base + 7 + static_offset as usize;
-        if !data_range.contains(&static_address) {
-            continue;
-        }
-
-        // Pointer to the reflection metadata
-        let metadata_addres = candidate_base + 19 + metadata_offset as usize;
-        if !data_range.contains(&metadata_addres) {
-            continue;
-        }
-
-        // Pointer to the name getter fn. char* get_singleton_name(metadata)
-        let fn_address = candidate_base + 24 + fn_offset as usize;
-        if !text_range.contains(&fn_address) {
-            continue;
-        }
-
+    for candidate in candidates {
         let get_singleton_name: extern "C" fn(usize) -> *const i8 = unsafe {
-            mem::transmute(fn_address)
+            mem::transmute(candidate.name_getter)
         };
 
         let cstr = unsafe {
-            std::ffi::CStr::from_ptr(get_singleton_name(metadata_addres))
+            std::ffi::CStr::from_ptr(get_singleton_name(candidate.metadata_address))
         };
 
         let name = cstr.to_str()
             .map_err(|_| SingletonMapError::MalformedName)?
             .to_string();
 
-        results.insert(name, static_address);
+        results.insert(name, candidate.static_address);
     }
 
     Ok(results)
@@ -1,55 +1,533 @@
 use std::error::Error;
 use std::fs;
 use std::path;
+use std::slice;
+use std::thread;
+use std::time;
 
-use clap::Parser;
-use dll_syringe::{Syringe, process::OwnedProcess};
+use clap::{Args, Parser, Subcommand};
+use fxr::Format;
+use host::{Agent, Session};
+use singletons::PeImage;
+use protocol::{Capability, ControlServerOptions, FxrPayload, PatchOptions, PatchOutcome};
+
+mod serve;
 
 #[derive(Parser, Debug)]
 #[command(name = "fxr-reloader-cli")]
 #[command(author = "Chainfailure")]
 #[command(version)]
-/// Reload in-memory FXRs with the supplied FXRS. 
+/// Reload in-memory FXRs with the supplied FXRS.
 ///
 /// IMPORTANT:
-/// This tool will not reload FXRs that are not in-memory and will not ensure 
-/// that patched FXRs persist when the game refetches them from the BDTs 
-/// itself.
-struct Args {
+/// FXRs that are not in-memory are held on to by the game until it loads them.
+/// Patched FXRs only persist when the game refetches them from the BDTs itself
+/// if they're patched with --persistent.
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Reload the supplied FXRs
+    Patch {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(required_unless_present = "archive")]
+        #[arg(num_args = 1..)]
+        /// The FXR files to be reloaded. JSON and XML FXRs are compiled
+        /// before they're sent over
+        fxrs: Vec<path::PathBuf>,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(requires = "ids")]
+        /// A BND4 archive, optionally DCX compressed, to take FXRs from
+        archive: Option<path::PathBuf>,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(requires = "archive")]
+        #[arg(num_args = 1..)]
+        /// The IDs of the FXRs to take from the archive
+        ids: Vec<u32>,
+
+        #[arg(long)]
+        /// Keep reloading the FXRs whenever the game loads them again
+        persistent: bool,
+
+        #[arg(long)]
+        /// Check the FXRs against the game and report what would happen
+        /// without reloading anything
+        dry_run: bool,
+
+        #[arg(long)]
+        #[arg(conflicts_with_all = ["archive", "dry_run"])]
+        /// Keep running and reload each FXR file again whenever it's saved
+        watch: bool,
+    },
+    /// Add FXRs under IDs that the game doesn't have loaded
+    Insert {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(required = true)]
+        #[arg(num_args = 1..)]
+        /// The FXR files to be inserted
+        fxrs: Vec<path::PathBuf>,
+    },
+    /// Put back the FXR definitions the game had loaded before they were
+    /// reloaded
+    Restore {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(required = true)]
+        #[arg(num_args = 1..)]
+        /// The IDs of the FXRs to restore
+        ids: Vec<u32>,
+
+        #[arg(short)]
+        #[arg(long)]
+        /// Reload the FXRs from this game archive instead. This also works
+        /// for FXRs the reloader has lost track of
+        archive: Option<path::PathBuf>,
+    },
+    /// Show how much memory the reloader is holding inside of the game
+    Stats {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Have the agent listen for requests from other tools on a named pipe,
+    /// and optionally on a loopback TCP port
+    Listen {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(long)]
+        #[arg(requires = "port")]
+        /// Only listen on the TCP port, not on the named pipe
+        no_pipe: bool,
+
+        #[arg(long)]
+        #[arg(num_args = 0..=1)]
+        // Has to match protocol::DEFAULT_CONTROL_PORT
+        #[arg(default_missing_value = "24873")]
+        /// Also listen on this TCP port on 127.0.0.1
+        port: Option<u16>,
+    },
+    /// Serve an HTTP API on 127.0.0.1 so editors can reload FXRs in the game
+    Serve {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(long)]
+        #[arg(default_value_t = 24874)]
+        /// The port to listen on
+        port: u16,
+    },
+    /// Show the agent's version and what it supports in the running game
+    Info {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Manage the FXRs the reloader holds on to until the game loads them
+    Overrides {
+        #[command(flatten)]
+        target: Target,
+
+        #[command(subcommand)]
+        command: OverridesCommand,
+    },
+    /// Convert an FXR between binary, JSON and XML. The formats are picked by
+    /// file extension, anything other than .json or .xml is binary
+    Convert {
+        /// The FXR to convert
+        input: path::PathBuf,
+
+        /// Where to write the converted FXR
+        output: path::PathBuf,
+    },
+    /// Compare an FXR file against the definition the game has loaded for it
+    Verify {
+        #[command(flatten)]
+        target: Target,
+
+        #[arg(short)]
+        #[arg(long)]
+        /// The ID of the loaded FXR to compare against, the file's ID if omitted
+        id: Option<u32>,
+
+        /// The FXR file that should be loaded
+        fxr: path::PathBuf,
+    },
+    /// List the textures, models, sounds and other FXRs an FXR refers to
+    Refs {
+        /// The FXR to list the references of
+        fxr: path::PathBuf,
+    },
+    /// Move an FXR to another ID
+    Reid {
+        /// The FXR to move
+        input: path::PathBuf,
+
+        #[arg(long)]
+        /// The new ID
+        id: u32,
+
+        #[arg(short)]
+        #[arg(long)]
+        /// Where to write the moved FXR, overwrites the input if omitted
+        output: Option<path::PathBuf>,
+    },
+    /// Update FXRs inside of a game archive, recompressing it
+    Pack {
+        /// The .ffxbnd.dcx archive to update
+        archive: path::PathBuf,
+
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(required = true)]
+        #[arg(num_args = 1..)]
+        /// The FXRs to put in the archive, each replaces the FXR with the
+        /// same ID. JSON and XML FXRs are compiled first
+        fxrs: Vec<path::PathBuf>,
+
+        #[arg(short)]
+        #[arg(long)]
+        /// Where to write the updated archive, overwrites the input if omitted
+        output: Option<path::PathBuf>,
+    },
+    /// Show what changed between two FXRs, node by node
+    Diff {
+        /// The original FXR
+        before: path::PathBuf,
+
+        /// The edited FXR
+        after: path::PathBuf,
+    },
+    /// Look into a game executable on disk, without running the game
+    Analyze {
+        #[command(subcommand)]
+        command: AnalyzeCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AnalyzeCommand {
+    /// List the singletons the agent can look up by name, along with the
+    /// address of their static
+    Singletons {
+        /// The game executable, eldenring.exe for example
+        executable: path::PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum OverridesCommand {
+    /// List pending and persistent overrides
+    List,
+    /// Stop overriding FXRs. Already patched FXRs stay patched until restored
+    Clear {
+        #[arg(short)]
+        #[arg(long)]
+        #[arg(num_args = 1..)]
+        /// The IDs of the FXRs to stop overriding, all of them if omitted
+        ids: Option<Vec<u32>>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct Target {
     #[arg(short)]
     #[arg(long)]
     /// The process ID of a running Elden Ring instance
     process_id: u32,
-    
-    #[arg(short)]
-    #[arg(long)]
-    #[arg(required = true)]
-    #[arg(num_args = 1..)]
-    /// The FXR files to be reloaded
-    fxrs: Vec<path::PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+    match Cli::parse().command {
+        Command::Patch { target, fxrs, archive, ids, persistent, dry_run, watch } => {
+            patch(target, fxrs, archive, ids, PatchOptions { persistent, dry_run }, watch)
+        },
+        Command::Insert { target, fxrs } => insert(target, fxrs),
+        Command::Restore { target, ids, archive } => restore(target, ids, archive),
+        Command::Stats { target } => stats(target),
+        Command::Info { target } => info(target),
+        Command::Listen { target, no_pipe, port } => listen(target, !no_pipe, port),
+        Command::Serve { target, port } => serve::serve(&Session::attach(target.process_id)?, port),
+        Command::Overrides { target, command } => overrides(target, command),
+        Command::Convert { input, output } => convert(input, output),
+        Command::Diff { before, after } => diff(before, after),
+        Command::Refs { fxr } => refs(fxr),
+        Command::Reid { input, id, output } => reid(input, id, output),
+        Command::Verify { target, id, fxr } => verify(target, id, fxr),
+        Command::Pack { archive, fxrs, output } => pack(archive, fxrs, output),
+        Command::Analyze { command: AnalyzeCommand::Singletons { executable } } => analyze_singletons(executable),
+    }
+}
+
+fn patch(
+    target: Target,
+    fxrs: Vec<path::PathBuf>,
+    archive: Option<path::PathBuf>,
+    ids: Vec<u32>,
+    options: PatchOptions,
+    watch: bool,
+) -> Result<(), Box<dyn Error>> {
+    let session = Session::attach(target.process_id)?;
+
+    let mut payloads = host::read_payloads(&fxrs)?;
+
+    if let Some(archive) = archive {
+        payloads.extend(host::archive_payloads(&archive, &ids)?);
+    }
+
+    send_patch(&session, payloads, &options)?;
+
+    if watch {
+        watch_fxrs(&session, &fxrs, &options);
+    }
+
+    Ok(())
+}
+
+/// How often watched FXR files are checked for changes.
+const WATCH_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+/// Reloads FXR files whenever they're saved, until the CLI is stopped. Files
+/// that can't be read or compiled are reported and picked up again on their
+/// next save, so a typo in a JSON FXR doesn't end the session.
+fn watch_fxrs(session: &Session, fxrs: &[path::PathBuf], options: &PatchOptions) -> ! {
+    println!("Watching {} FXRs for changes, press Ctrl+C to stop", fxrs.len());
 
-    let target_process = OwnedProcess::from_pid(args.process_id)?;
+    let mut modified = fxrs.iter().map(|f| modified_time(f)).collect::<Vec<_>>();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
 
-    // Obtain an instance of the agent DLL in the remote process
-    let syringe = Syringe::for_process(target_process);
-    let agent_module = syringe.find_or_inject(protocol::AGENT_DLL_NAME)?;
+        for (fxr, last) in fxrs.iter().zip(&mut modified) {
+            let current = modified_time(fxr);
+            if current == *last {
+                continue;
+            }
+            *last = current;
 
-    let remote_fn = unsafe {
-        syringe.get_payload_procedure::<fn(Vec<Vec<u8>>)>(agent_module, "PatchFxr")
-    }.unwrap().unwrap();
+            let result = match host::read_payloads(slice::from_ref(fxr)) {
+                Ok(payloads) => send_patch(session, payloads, options),
+                Err(e) => Err(e.into()),
+            };
+
+            if let Err(e) = result {
+                println!("Could not reload {}: {e}", fxr.display());
+            }
+        }
+    }
+}
+
+fn modified_time(path: &path::Path) -> Option<time::SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn send_patch(session: &Session, payloads: Vec<FxrPayload>, options: &PatchOptions) -> Result<(), Box<dyn Error>> {
+    for (parent, child) in session.missing_children(&payloads)? {
+        println!("Warning: FXR {parent} spawns FXR {child} which the game doesn't have loaded");
+    }
+
+    // The agent stays loaded, it hooks the game to apply the swaps
+    for outcome in session.patch(payloads, options)? {
+        match outcome {
+            PatchOutcome::Patched(id) => println!("Reloaded FXR {id}"),
+            PatchOutcome::Pending(id) => println!("FXR {id} is not loaded, it will be reloaded once the game loads it"),
+            PatchOutcome::WouldPatch(id) => println!("FXR {id} would be reloaded"),
+            PatchOutcome::WouldQueue(id) => println!("FXR {id} is not loaded, it would be reloaded once the game loads it"),
+        }
+    }
+
+    Ok(())
+}
+
+fn insert(target: Target, fxrs: Vec<path::PathBuf>) -> Result<(), Box<dyn Error>> {
+    let session = Session::attach(target.process_id)?;
+
+    session.insert(host::read_payloads(&fxrs)?)?;
+
+    Ok(())
+}
 
-    let file_contents = args.fxrs.iter()
-        .map(fs::read)
+fn restore(target: Target, ids: Vec<u32>, archive: Option<path::PathBuf>) -> Result<(), Box<dyn Error>> {
+    let session = Session::attach(target.process_id)?;
+
+    match archive {
+        // Stock FXRs straight from the game's archive
+        Some(archive) => {
+            let payloads = host::archive_payloads(&archive, &ids)?;
+            send_patch(&session, payloads, &PatchOptions::default())
+        },
+        None => Ok(session.restore(ids)?),
+    }
+}
+
+fn stats(target: Target) -> Result<(), Box<dyn Error>> {
+    let stats = Session::attach(target.process_id)?.memory_stats()?;
+
+    println!("Reloaded FXRs: {}", stats.tracked_fxrs);
+    println!("Bytes in use: {}", stats.live_bytes);
+    println!("Bytes waiting to be freed: {}", stats.retired_bytes);
+
+    Ok(())
+}
+
+fn info(target: Target) -> Result<(), Box<dyn Error>> {
+    let session = Session::attach(target.process_id)?;
+    let agent = session.agent();
+    let game = session.capabilities();
+
+    println!("Agent: {} (protocol {})", agent.agent_version, agent.protocol_version);
+    println!("Game: {} {}", game.game, game.version);
+    for capability in Capability::ALL {
+        let supported = if game.supports(capability) { "yes" } else { "no" };
+        println!("{capability}: {supported}");
+    }
+
+    Ok(())
+}
+
+fn listen(target: Target, pipe: bool, port: Option<u16>) -> Result<(), Box<dyn Error>> {
+    Session::attach(target.process_id)?.start_control_server(ControlServerOptions { pipe, tcp_port: port })?;
+
+    if pipe {
+        println!("Agent is listening on {}", protocol::pipe_name(target.process_id));
+    }
+    if let Some(port) = port {
+        println!("Agent is listening on 127.0.0.1:{port}");
+    }
+
+    Ok(())
+}
+
+fn overrides(target: Target, command: OverridesCommand) -> Result<(), Box<dyn Error>> {
+    let session = Session::attach(target.process_id)?;
+
+    match command {
+        OverridesCommand::List => {
+            for entry in session.list_overrides()? {
+                let kind = match entry.persistent {
+                    true => "persistent",
+                    false => "pending",
+                };
+
+                println!("{} ({kind}, {} bytes)", entry.fxr_id, entry.size);
+            }
+        },
+        OverridesCommand::Clear { ids } => session.clear_overrides(ids)?,
+    }
+
+    Ok(())
+}
+
+fn convert(input: path::PathBuf, output: path::PathBuf) -> Result<(), Box<dyn Error>> {
+    let fxr = fxr::read_file(&input)?;
+
+    fs::write(&output, fxr.encode(Format::from_path(&output))?)?;
+
+    Ok(())
+}
+
+fn diff(before: path::PathBuf, after: path::PathBuf) -> Result<(), Box<dyn Error>> {
+    let changes = fxr::diff(&fxr::read_file(&before)?, &fxr::read_file(&after)?);
+    if changes.is_empty() {
+        println!("No differences");
+    }
+
+    for change in changes {
+        println!("{change}");
+    }
+
+    Ok(())
+}
+
+fn verify(target: Target, id: Option<u32>, fxr: path::PathBuf) -> Result<(), Box<dyn Error>> {
+    let expected = fxr::read_file(&fxr)?;
+    let id = id.unwrap_or(expected.id);
+
+    let dump = Session::attach(target.process_id)?.dump_fxr(id)?;
+    let loaded = fxr::Fxr::read_relocated(&dump.bytes, dump.address)?;
+
+    let changes = fxr::diff(&expected, &loaded);
+    if changes.is_empty() {
+        println!("FXR {id} as loaded by the game matches {}", fxr.display());
+        return Ok(());
+    }
+
+    println!("FXR {id} as loaded by the game differs from {}:", fxr.display());
+    for change in changes {
+        println!("{change}");
+    }
+
+    Ok(())
+}
+
+fn refs(fxr: path::PathBuf) -> Result<(), Box<dyn Error>> {
+    let fxr = fxr::read_file(&fxr)?;
+
+    for reference in fxr::references(&fxr) {
+        println!("{reference}");
+    }
+
+    Ok(())
+}
+
+fn reid(input: path::PathBuf, id: u32, output: Option<path::PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut fxr = fxr::read_file(&input)?;
+    let previous = fxr.id;
+    fxr.reid(id);
+
+    let output = output.unwrap_or(input);
+    fs::write(&output, fxr.encode(Format::from_path(&output))?)?;
+
+    println!("Moved FXR {previous} to {id}");
+
+    Ok(())
+}
+
+fn pack(archive: path::PathBuf, fxrs: Vec<path::PathBuf>, output: Option<path::PathBuf>) -> Result<(), Box<dyn Error>> {
+    let file_contents = fxrs.iter()
+        .map(|f| fxr::read_binary(f))
         .collect::<Result<Vec<_>, _>>()?;
 
-    remote_fn.call(&file_contents)?;
+    let output = output.unwrap_or_else(|| archive.clone());
+    fxr::pack_archive_fxrs(&archive, file_contents, &output)?;
+
+    println!("Packed {} FXRs into {}", fxrs.len(), output.display());
+
+    Ok(())
+}
+
+fn analyze_singletons(executable: path::PathBuf) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(&executable)?;
+    let mut found = PeImage::parse(&bytes)?.singletons()?;
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for singleton in &found {
+        match &singleton.name {
+            Some(name) => println!("{:#x} {name}", singleton.candidate.static_address),
+            None => println!(
+                "{:#x} <unnamed, metadata at {:#x}>",
+                singleton.candidate.static_address,
+                singleton.candidate.metadata_address,
+            ),
+        }
+    }
 
-    // Remove agent DLL from remote process memory again
-    syringe.eject(agent_module)?;
+    println!("Found {} singletons", found.len());
 
     Ok(())
 }
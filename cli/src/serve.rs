@@ -0,0 +1,326 @@
+use std::error::Error;
+use std::io::Read;
+use std::net::Ipv4Addr;
+
+use fxr::{Format, Fxr};
+use host::{Agent, SessionError};
+use protocol::{FxrPayload, PatchFxrError, PatchOptions, MAX_FRAME_SIZE};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Header editors can set to name the FXR they're sending, it shows up in
+/// errors from the agent.
+const SOURCE_HEADER: &str = "X-Fxr-Source";
+
+/// Runs a small HTTP API on the loopback interface so editors can have the
+/// game reload an FXR without going through the CLI or GUI. Requests are
+/// handled one at a time against a single agent.
+pub(crate) fn serve(agent: &dyn Agent, port: u16) -> Result<(), Box<dyn Error>> {
+    let server = Server::http((Ipv4Addr::LOCALHOST, port)).map_err(|e| e as Box<dyn Error>)?;
+    println!("Listening on http://127.0.0.1:{port}");
+
+    run(agent, &server)
+}
+
+/// Answers requests until the server is unblocked.
+fn run(agent: &dyn Agent, server: &Server) -> Result<(), Box<dyn Error>> {
+    let port = server.server_addr().to_ip().map_or(0, |a| a.port());
+
+    for mut request in server.incoming_requests() {
+        let result = check_caller(&request, port).and_then(|_| handle(agent, &mut request));
+        let (status, body) = match result {
+            Ok(body) => (200, body),
+            Err(e) => (e.status, json!({ "error": e.message })),
+        };
+
+        let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_data(serde_json::to_vec(&body)?)
+            .with_status_code(status)
+            .with_header(content_type);
+
+        // The client hanging up early is their problem
+        let _ = request.respond(response);
+    }
+
+    Ok(())
+}
+
+/// Refuses requests from web pages. Browsers name the page in an `Origin`
+/// header, which editors have no reason to send, and a page that rebound its
+/// domain to the loopback interface still sends that domain as the `Host`.
+fn check_caller(request: &Request, port: u16) -> Result<(), ApiError> {
+    if header(request, "Origin").is_some() {
+        return Err(ApiError::forbidden("Requests from web pages are not accepted."));
+    }
+
+    let host = header(request, "Host");
+    if host != Some(format!("127.0.0.1:{port}")) && host != Some(format!("localhost:{port}")) {
+        return Err(ApiError::forbidden("Host has to be the loopback address the API listens on."));
+    }
+
+    Ok(())
+}
+
+/// Routes:
+///  - `POST /fxr` reloads the FXR in the body. Binary unless the content type
+///    says JSON or XML. Takes `persistent` and `dry_run` query flags.
+///  - `GET /fxrs` lists the IDs of the FXRs the game has loaded.
+///  - `POST /restore/{id}` puts back the game's own definition.
+fn handle(agent: &dyn Agent, request: &mut Request) -> Result<Value, ApiError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    match (request.method(), path) {
+        (Method::Post, "/fxr") => {
+            let payload = read_payload(request)?;
+            let options = PatchOptions {
+                persistent: flag(query, "persistent"),
+                dry_run: flag(query, "dry_run"),
+            };
+
+            let payloads = vec![payload];
+            let missing_children = agent.missing_children(&payloads)?;
+            let outcomes = agent.patch(payloads, &options)?;
+
+            Ok(json!({ "outcomes": outcomes, "missing_children": missing_children }))
+        },
+        (Method::Get, "/fxrs") => Ok(json!(agent.resident_fxrs()?)),
+        (Method::Post, _) if path.starts_with("/restore/") => {
+            let fxr_id = path["/restore/".len()..].parse::<u32>()
+                .map_err(|_| ApiError::bad_request("FXR ID is not a number."))?;
+
+            agent.restore(vec![fxr_id])?;
+            Ok(json!({}))
+        },
+        _ => Err(ApiError { status: 404, message: format!("No route for {} {path}.", request.method()) }),
+    }
+}
+
+/// Reads the FXR out of the request body, compiling it if it was sent as
+/// JSON or XML. Bodies the agent wouldn't take over its control channels
+/// either are refused before they're read in full.
+fn read_payload(request: &mut Request) -> Result<FxrPayload, ApiError> {
+    if request.body_length().is_some_and(|l| l > MAX_FRAME_SIZE) {
+        return Err(ApiError::too_large());
+    }
+
+    let source = header(request, SOURCE_HEADER);
+    let format = match header(request, "Content-Type").as_deref() {
+        Some(t) if t.starts_with("application/json") => Format::Json,
+        Some(t) if t.starts_with("application/xml") || t.starts_with("text/xml") => Format::Xml,
+        _ => Format::Binary,
+    };
+
+    let mut bytes = Vec::new();
+    request.as_reader().take(MAX_FRAME_SIZE as u64 + 1).read_to_end(&mut bytes)
+        .map_err(|e| ApiError::bad_request(&e.to_string()))?;
+
+    if bytes.len() > MAX_FRAME_SIZE {
+        return Err(ApiError::too_large());
+    }
+
+    if format != Format::Binary {
+        bytes = Fxr::decode(&bytes, format)
+            .map_err(|e| ApiError::bad_request(&e.to_string()))?
+            .to_bytes();
+    }
+
+    Ok(FxrPayload::new(source, bytes))
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request.headers().iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.to_string())
+}
+
+/// Query flags are set by their presence, unless they're explicitly `false`
+/// or `0`.
+fn flag(query: &str, name: &str) -> bool {
+    query.split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then_some(value)
+        })
+        .any(|value| value != "false" && value != "0")
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: &str) -> Self {
+        Self { status: 400, message: message.to_string() }
+    }
+
+    fn forbidden(message: &str) -> Self {
+        Self { status: 403, message: message.to_string() }
+    }
+
+    fn too_large() -> Self {
+        Self { status: 413, message: format!("FXRs can be at most {MAX_FRAME_SIZE} bytes.") }
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(error: SessionError) -> Self {
+        let status = match error {
+            // The FXR isn't where the request expects it to be
+            SessionError::Patch(PatchFxrError::NotPatched(_) | PatchFxrError::NotLoaded(_)) => 404,
+            SessionError::Patch(PatchFxrError::AlreadyLoaded(_)) => 409,
+            // Something wrong with what was sent rather than with the game
+            SessionError::Compatibility(_)
+            | SessionError::Unsupported(..)
+            | SessionError::Patch(PatchFxrError::InvalidFxr | PatchFxrError::Unsupported(_)) => 422,
+            _ => 500,
+        };
+
+        Self { status, message: error.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    use host::MockAgent;
+    use protocol::RunningGame;
+
+    use super::*;
+
+    fn fxr(id: u32, unk08: u32) -> Fxr {
+        let mut fxr = Fxr::read(include_bytes!("../../fxr/tests/fixtures/aligned.fxr")).unwrap();
+        fxr.id = id;
+        fxr.unk08 = unk08;
+
+        // Elden Ring refuses FXRs that only use actions Sekiro already had
+        let mut lens_flare = fxr.actions[0].clone();
+        lens_flare.kind = 10014;
+        fxr.actions.push(lens_flare);
+        fxr
+    }
+
+    /// Runs the API against the agent on a port of its own while `test`
+    /// makes requests to it.
+    fn with_server(agent: &MockAgent, test: impl FnOnce(u16)) {
+        let server = Server::http((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+
+        thread::scope(|s| {
+            s.spawn(|| run(agent, &server).unwrap());
+
+            // Stops the server even if the test fails, the scope waits for it
+            let _unblock = Unblock(&server);
+            test(port);
+        });
+    }
+
+    struct Unblock<'a>(&'a Server);
+
+    impl Drop for Unblock<'_> {
+        fn drop(&mut self) {
+            self.0.unblock();
+        }
+    }
+
+    /// Sends a request made of the supplied request line and headers, and
+    /// yields the status and JSON body of the response.
+    fn send(port: u16, head: &str, body: &[u8]) -> (u16, Value) {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        write!(stream, "{head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+
+        (head[9..12].parse().unwrap(), serde_json::from_str(body).unwrap())
+    }
+
+    fn call(port: u16, request: &str, body: &[u8]) -> (u16, Value) {
+        send(port, &format!("{request} HTTP/1.1\r\nHost: 127.0.0.1:{port}"), body)
+    }
+
+    #[test]
+    fn patches_lists_and_restores_fxrs() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, 0).to_bytes());
+        let edited = fxr(300, 1).to_bytes();
+
+        with_server(&agent, |port| {
+            let (status, body) = call(port, "POST /fxr", &edited);
+            assert_eq!(status, 200);
+            assert_eq!(body, json!({ "outcomes": [{ "Patched": 300 }], "missing_children": [] }));
+            assert_eq!(agent.definition(300), Some(edited.clone()));
+
+            assert_eq!(call(port, "GET /fxrs", &[]), (200, json!([300])));
+
+            assert_eq!(call(port, "POST /restore/300", &[]), (200, json!({})));
+            assert_eq!(agent.definition(300), Some(fxr(300, 0).to_bytes()));
+
+            // Nothing left to restore
+            assert_eq!(call(port, "POST /restore/300", &[]).0, 404);
+            assert_eq!(call(port, "POST /restore/fxr", &[]).0, 400);
+            assert_eq!(call(port, "DELETE /fxrs", &[]).0, 404);
+        });
+    }
+
+    #[test]
+    fn compiles_fxrs_sent_as_text() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, 0).to_bytes());
+        let edited = fxr(300, 1);
+        let json = edited.encode(Format::Json).unwrap();
+
+        with_server(&agent, |port| {
+            let head = format!("POST /fxr?dry_run HTTP/1.1\r\nHost: localhost:{port}\r\nContent-Type: application/json");
+            let (status, body) = send(port, &head, &json);
+            assert_eq!(status, 200);
+            assert_eq!(body["outcomes"], json!([{ "WouldPatch": 300 }]));
+            assert_eq!(agent.definition(300), Some(fxr(300, 0).to_bytes()));
+
+            // Without a content type it's taken as a binary FXR
+            assert_eq!(call(port, "POST /fxr", json.as_slice()).0, 422);
+        });
+    }
+
+    #[test]
+    fn refuses_oversized_fxrs() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, 0).to_bytes());
+
+        with_server(&agent, |port| {
+            // Refused going by the length alone, without sending the body
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            write!(
+                stream,
+                "POST /fxr HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                MAX_FRAME_SIZE + 1,
+            ).unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 413"));
+        });
+    }
+
+    #[test]
+    fn refuses_requests_from_web_pages() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, 0).to_bytes());
+
+        with_server(&agent, |port| {
+            let head = format!("GET /fxrs HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nOrigin: https://example.com");
+            assert_eq!(send(port, &head, &[]).0, 403);
+
+            // A rebound domain still names itself as the host
+            let head = format!("GET /fxrs HTTP/1.1\r\nHost: example.com:{port}");
+            assert_eq!(send(port, &head, &[]).0, 403);
+
+            let head = format!("GET /fxrs HTTP/1.1\r\nHost: localhost:{}", port + 1);
+            assert_eq!(send(port, &head, &[]).0, 403);
+
+            assert_eq!(send(port, &format!("GET /fxrs HTTP/1.1\r\nHost: localhost:{port}"), &[]).0, 200);
+        });
+    }
+}
@@ -0,0 +1,207 @@
+use crate::reader::Reader;
+use crate::writer::Writer;
+
+use super::ArchiveError;
+
+const MAGIC: &[u8; 4] = b"BND4";
+
+/// Offset of the first entry, right after the header.
+const ENTRIES_OFFSET: usize = 0x40;
+
+/// Bits of the format byte, once put in the right order.
+const FORMAT_IDS: u8 = 0x02;
+const FORMAT_NAMES1: u8 = 0x04;
+const FORMAT_NAMES2: u8 = 0x08;
+const FORMAT_LONG_OFFSETS: u8 = 0x10;
+const FORMAT_COMPRESSION: u8 = 0x20;
+
+/// Alignment of the file data when writing the archive back out.
+const DATA_ALIGNMENT: usize = 0x10;
+
+/// The archive format the games bundle their FXRs in.
+#[derive(Debug, Clone)]
+pub struct Bnd4 {
+    pub files: Vec<BndFile>,
+    /// Everything before the file data, kept as is so the names, hashes
+    /// and flags we don't look at survive writing the archive back out.
+    headers: Vec<u8>,
+    format: u8,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Where the entry sits in the headers.
+    position: usize,
+    /// Where the file's data was when the archive was read.
+    data_offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BndFile {
+    pub id: Option<i32>,
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Bnd4 {
+    pub fn read(bytes: &[u8]) -> Result<Self, ArchiveError> {
+        let reader = Reader::new(bytes);
+        if reader.bytes(0x0, 0x4)? != MAGIC {
+            return Err(ArchiveError::Magic);
+        }
+
+        if reader.u8(0x9)? != 0 {
+            return Err(ArchiveError::BigEndian);
+        }
+
+        let file_count = reader.u32(0xc)? as usize;
+        let entry_size = reader.u64(0x20)? as usize;
+        let headers_end = reader.u64(0x28)? as usize;
+        let unicode = reader.u8(0x30)? != 0;
+        let format = decode_format(reader.u8(0x31)?, reader.u8(0xa)? != 0);
+
+        // Counts and sizes straight from the file, so make sure the entries
+        // are actually there before going through them
+        let entries_end = file_count.checked_mul(entry_size)
+            .and_then(|size| size.checked_add(ENTRIES_OFFSET))
+            .filter(|end| *end <= bytes.len() && (entry_size != 0 || file_count == 0))
+            .ok_or(ArchiveError::Entries(file_count, entry_size))?;
+
+        // The entries are rewritten in place so they have to be part of the
+        // headers we keep around
+        let headers_end = headers_end.max(entries_end);
+
+        let (files, entries) = (0..file_count)
+            .map(|i| read_file(&reader, ENTRIES_OFFSET + i * entry_size, i, format, unicode))
+            .collect::<Result<(Vec<_>, Vec<_>), _>>()?;
+
+        Ok(Self {
+            files,
+            headers: reader.bytes(0x0, headers_end)?.to_vec(),
+            format,
+            entries,
+        })
+    }
+
+    /// Writes the archive with the files' current contents. Files are laid
+    /// out in the order they were in, the entries are updated to match.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes(&self.headers);
+
+        let mut order = (0..self.files.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| self.entries[*i].data_offset);
+
+        let mut data_offsets = vec![0; self.files.len()];
+        for i in order {
+            writer.align(DATA_ALIGNMENT);
+            data_offsets[i] = writer.position();
+            writer.bytes(&self.files[i].data);
+        }
+
+        let mut bytes = writer.finish();
+        for (i, file) in self.files.iter().enumerate() {
+            let size = file.data.len() as u64;
+            let mut position = self.entries[i].position + 0x8;
+
+            put(&mut bytes, &mut position, &size.to_le_bytes());
+            if self.format & FORMAT_COMPRESSION != 0 {
+                put(&mut bytes, &mut position, &size.to_le_bytes());
+            }
+
+            match self.format & FORMAT_LONG_OFFSETS != 0 {
+                true => put(&mut bytes, &mut position, &(data_offsets[i] as u64).to_le_bytes()),
+                false => put(&mut bytes, &mut position, &(data_offsets[i] as u32).to_le_bytes()),
+            }
+        }
+
+        bytes
+    }
+}
+
+/// Overwrites the bytes at `position` and moves past what was written.
+fn put(target: &mut [u8], position: &mut usize, bytes: &[u8]) {
+    target[*position..*position + bytes.len()].copy_from_slice(bytes);
+    *position += bytes.len();
+}
+
+fn read_file(
+    reader: &Reader,
+    entry: usize,
+    index: usize,
+    format: u8,
+    unicode: bool,
+) -> Result<(BndFile, Entry), ArchiveError> {
+    let compressed_size = reader.u64(entry + 0x8)? as usize;
+    let mut position = entry + 0x10;
+
+    if format & FORMAT_COMPRESSION != 0 {
+        let uncompressed_size = reader.u64(position)? as usize;
+        if uncompressed_size != compressed_size {
+            return Err(ArchiveError::CompressedEntry(index));
+        }
+
+        position += 0x8;
+    }
+
+    let data_offset = if format & FORMAT_LONG_OFFSETS != 0 {
+        position += 0x8;
+        reader.u64(position - 0x8)? as usize
+    } else {
+        position += 0x4;
+        reader.u32(position - 0x4)? as usize
+    };
+
+    let id = if format & FORMAT_IDS != 0 {
+        position += 0x4;
+        Some(reader.u32(position - 0x4)? as i32)
+    } else {
+        None
+    };
+
+    let name = if format & (FORMAT_NAMES1 | FORMAT_NAMES2) != 0 {
+        Some(read_name(reader, reader.u32(position)? as usize, unicode)?)
+    } else {
+        None
+    };
+
+    let file = BndFile {
+        id,
+        name,
+        data: reader.bytes(data_offset, compressed_size)?.to_vec(),
+    };
+
+    Ok((file, Entry { position: entry, data_offset }))
+}
+
+/// Names are null terminated, either UTF-16 or Shift-JIS. The games only use
+/// ASCII in the latter so it's read as such.
+fn read_name(reader: &Reader, offset: usize, unicode: bool) -> Result<String, ArchiveError> {
+    if unicode {
+        let units = (0..)
+            .map(|i| reader.u16(offset + i * 0x2))
+            .take_while(|u| !matches!(u, Ok(0)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(String::from_utf16_lossy(&units));
+    }
+
+    let bytes = (0..)
+        .map(|i| reader.u8(offset + i))
+        .take_while(|b| !matches!(b, Ok(0)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The format byte is stored with its bits reversed unless it's flagged as
+/// big endian, in which case it's stored as is.
+fn decode_format(raw: u8, bit_big_endian: bool) -> u8 {
+    let reversed = bit_big_endian || (raw & 0x1 != 0 && raw & 0x80 == 0);
+
+    match reversed {
+        true => raw,
+        false => raw.reverse_bits(),
+    }
+}
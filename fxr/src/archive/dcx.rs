@@ -0,0 +1,83 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::reader::Reader;
+
+use super::ArchiveError;
+
+const MAGIC: &[u8; 4] = b"DCX\0";
+const DEFLATE: &[u8; 4] = b"DFLT";
+
+/// Offset of the DCP block, which names the compression.
+const DCP_OFFSET: usize = 0x24;
+
+/// Whether the bytes are a DCX container, which the games compress their
+/// archives with.
+pub(crate) fn is_dcx(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Header of a DCX container, kept as read so recompressing an archive
+/// yields a container the games take.
+#[derive(Debug, Clone)]
+pub(crate) struct DcxHeader {
+    bytes: Vec<u8>,
+}
+
+/// Unpacks a DCX container. Everything in its header is big endian.
+pub(crate) fn decompress(bytes: &[u8]) -> Result<(DcxHeader, Vec<u8>), ArchiveError> {
+    let reader = Reader::new(bytes);
+    if reader.bytes(0x0, 0x4)? != MAGIC {
+        return Err(ArchiveError::Magic);
+    }
+
+    let uncompressed_size = reader.u32_be(0x1c)? as usize;
+    let compressed_size = reader.u32_be(0x20)? as usize;
+
+    let format = reader.bytes(DCP_OFFSET + 0x4, 0x4)?;
+    if format != DEFLATE {
+        return Err(ArchiveError::UnsupportedCompression(
+            String::from_utf8_lossy(format).into_owned(),
+        ));
+    }
+
+    // The DCP block is followed by the DCA block, which is followed by the
+    // data. Both store their own size.
+    let dca = DCP_OFFSET + reader.u32_be(DCP_OFFSET + 0x8)? as usize;
+    let data = dca + reader.u32_be(dca + 0x4)? as usize;
+
+    // The stored size is only a hint, zlib doesn't expand data by more than
+    // about 1032 times so nothing past that is reserved up front
+    let compressed = reader.bytes(data, compressed_size)?;
+    let mut decompressed = Vec::with_capacity(uncompressed_size.min(compressed.len().saturating_mul(1032)));
+    ZlibDecoder::new(compressed)
+        .read_to_end(&mut decompressed)
+        .map_err(ArchiveError::Decompress)?;
+
+    let header = DcxHeader {
+        bytes: reader.bytes(0x0, data)?.to_vec(),
+    };
+
+    Ok((header, decompressed))
+}
+
+impl DcxHeader {
+    /// Packs the data into a DCX container with this header, updating the
+    /// sizes it stores.
+    pub(crate) fn compress(&self, data: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+        // The games' own archives are compressed at the highest level
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data).map_err(ArchiveError::Compress)?;
+        let compressed = encoder.finish().map_err(ArchiveError::Compress)?;
+
+        let mut bytes = self.bytes.clone();
+        bytes[0x1c..0x20].copy_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes[0x20..0x24].copy_from_slice(&(compressed.len() as u32).to_be_bytes());
+        bytes.extend(compressed);
+
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{Header, ReadError};
+
+mod bnd4;
+mod dcx;
+
+use dcx::DcxHeader;
+
+pub use bnd4::{Bnd4, BndFile};
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("File is not a BND4 or DCX archive.")]
+    Magic,
+    #[error("DCX compression {0} is not supported.")]
+    UnsupportedCompression(String),
+    #[error("Big endian archives are not supported.")]
+    BigEndian,
+    #[error("Archive has {0} entries of {1:#x} bytes, which don't fit in it.")]
+    Entries(usize, usize),
+    #[error("Archive entry {0} is compressed on its own, which is not supported.")]
+    CompressedEntry(usize),
+    #[error("Archive is malformed. {0}")]
+    Malformed(#[from] ReadError),
+    #[error("Failed to decompress archive. {0}")]
+    Decompress(std::io::Error),
+    #[error("Failed to compress archive. {0}")]
+    Compress(std::io::Error),
+    #[error("Failed to read archive. {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Archive does not contain FXR {0}.")]
+    MissingFxr(u32),
+    #[error("Supplied file is not an FXR. {0}")]
+    InvalidFxr(ReadError),
+}
+
+/// A BND4 archive, optionally wrapped in DCX compression like the games'
+/// `.ffxbnd.dcx` files.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub bnd: Bnd4,
+    /// Set if the archive was DCX compressed.
+    dcx: Option<DcxHeader>,
+}
+
+impl Archive {
+    pub fn read(bytes: &[u8]) -> Result<Self, ArchiveError> {
+        if !dcx::is_dcx(bytes) {
+            return Ok(Self { bnd: Bnd4::read(bytes)?, dcx: None });
+        }
+
+        let (header, decompressed) = dcx::decompress(bytes)?;
+        Ok(Self { bnd: Bnd4::read(&decompressed)?, dcx: Some(header) })
+    }
+
+    pub fn open(path: &Path) -> Result<Self, ArchiveError> {
+        Self::read(&std::fs::read(path)?)
+    }
+
+    /// Writes the archive back out, compressing it again if it was read from
+    /// a DCX container.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ArchiveError> {
+        let bnd = self.bnd.to_bytes();
+
+        match &self.dcx {
+            Some(header) => header.compress(&bnd),
+            None => Ok(bnd),
+        }
+    }
+
+    /// Swaps out the archive's copy of an FXR, the FXR to replace is picked
+    /// by the ID in the supplied FXR's header.
+    pub fn replace_fxr(&mut self, fxr: Vec<u8>) -> Result<(), ArchiveError> {
+        let fxr_id = Header::read(&fxr).map_err(ArchiveError::InvalidFxr)?.id;
+
+        let file = self.bnd.files.iter_mut()
+            .find(|f| Header::read(&f.data).is_ok_and(|h| h.id == fxr_id))
+            .ok_or(ArchiveError::MissingFxr(fxr_id))?;
+
+        file.data = fxr;
+        Ok(())
+    }
+
+    /// The FXRs in the archive along with their IDs. Files that aren't FXRs
+    /// are skipped.
+    pub fn fxrs(&self) -> impl Iterator<Item = (u32, &BndFile)> {
+        self.bnd.files.iter()
+            .filter_map(|f| Header::read(&f.data).ok().map(|h| (h.id, f)))
+    }
+
+    pub fn fxr(&self, fxr_id: u32) -> Result<&[u8], ArchiveError> {
+        self.fxrs()
+            .find(|(id, _)| *id == fxr_id)
+            .map(|(_, f)| f.data.as_slice())
+            .ok_or(ArchiveError::MissingFxr(fxr_id))
+    }
+}
+
+/// Pulls the FXRs with the supplied IDs out of an archive.
+pub fn read_archive_fxrs(path: &Path, fxr_ids: &[u32]) -> Result<Vec<Vec<u8>>, ArchiveError> {
+    let archive = Archive::open(path)?;
+
+    fxr_ids.iter()
+        .map(|id| archive.fxr(*id).map(<[u8]>::to_vec))
+        .collect()
+}
+
+/// Updates the FXRs inside of an archive with the supplied ones and writes it
+/// to `output`, which may be the archive itself. Every FXR has to replace one
+/// that's already in the archive.
+pub fn pack_archive_fxrs(archive: &Path, fxrs: Vec<Vec<u8>>, output: &Path) -> Result<(), ArchiveError> {
+    let mut archive = Archive::open(archive)?;
+
+    for fxr in fxrs {
+        archive.replace_fxr(fxr)?;
+    }
+
+    std::fs::write(output, archive.to_bytes()?)?;
+    Ok(())
+}
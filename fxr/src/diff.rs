@@ -0,0 +1,553 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::model::{span, Action, Effect, Field, Link, Modifier, ModifierProperty, Property};
+use crate::Fxr;
+
+/// A single difference between two FXRs, located by its path. Paths all start
+/// at the FXR itself: `/unk04` for the header, `/state[0]/transition[1]` for
+/// records outside of the node tree and `/root/node[0]/action[2]` for the
+/// node tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    Changed { before: String, after: String },
+    Added,
+    Removed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ChangeKind::Changed { before, after } => write!(f, "{}: {before} -> {after}", self.path),
+            ChangeKind::Added => write!(f, "{}: added", self.path),
+            ChangeKind::Removed => write!(f, "{}: removed", self.path),
+        }
+    }
+}
+
+/// Compares two FXRs by structure rather than by bytes. Records are matched up
+/// by their position under their parent so inserting a node in the middle
+/// shows up as every node after it changing. Every node is compared once,
+/// nodes that can't be reached from the root are paired up by their position
+/// among the other unreachable ones.
+pub fn diff(before: &Fxr, after: &Fxr) -> Vec<Change> {
+    let mut differ = Differ {
+        before,
+        after,
+        visited_before: HashSet::new(),
+        visited_after: HashSet::new(),
+        changes: Vec::new(),
+    };
+
+    differ.value("/id", &before.id, &after.id);
+    differ.value("/version", &before.version, &after.version);
+    differ.value("/unk04", &before.unk04, &after.unk04);
+    differ.value("/unk08", &before.unk08, &after.unk08);
+    differ.value("/unk68", &before.unk68, &after.unk68);
+    differ.value("/unk6c", &before.unk6c, &after.unk6c);
+    differ.value("/unk88", &before.unk88, &after.unk88);
+    differ.value("/unk8c", &before.unk8c, &after.unk8c);
+    differ.state_machines();
+    differ.states();
+
+    match (before.root(), after.root()) {
+        (Some(_), Some(_)) => differ.node("/root", 0, 0),
+        (Some(_), None) => differ.removed_node("/root", 0),
+        (None, Some(_)) => differ.added_node("/root", 0),
+        (None, None) => {},
+    }
+
+    differ.unreachable_nodes();
+    differ.words("section12", &before.section12, &after.section12);
+    differ.words("section13", &before.section13, &after.section13);
+    differ.words("section14", &before.section14, &after.section14);
+
+    differ.changes
+}
+
+struct Differ<'a> {
+    before: &'a Fxr,
+    after: &'a Fxr,
+    /// Nodes already compared or reported, by index.
+    visited_before: HashSet<usize>,
+    visited_after: HashSet<usize>,
+    changes: Vec<Change>,
+}
+
+/// Compares the listed scalar members of two records.
+macro_rules! members {
+    ($differ:expr, $path:expr, $a:expr, $b:expr, [$($member:ident),* $(,)?]) => {
+        $(
+            $differ.value(&format!("{}.{}", $path, stringify!($member)), &$a.$member, &$b.$member);
+        )*
+    };
+}
+
+impl<'a> Differ<'a> {
+    fn push(&mut self, path: &str, kind: ChangeKind) {
+        self.changes.push(Change {
+            path: path.to_string(),
+            kind,
+        });
+    }
+
+    fn value<T: PartialEq + fmt::Debug>(&mut self, path: &str, before: &T, after: &T) {
+        if before != after {
+            self.push(path, ChangeKind::Changed {
+                before: format!("{before:?}"),
+                after: format!("{after:?}"),
+            });
+        }
+    }
+
+    /// Pairs up two runs of records by index, reporting the ones only one of
+    /// the sides has.
+    fn runs<T>(
+        &mut self,
+        path: &str,
+        name: &str,
+        before: &'a [T],
+        after: &'a [T],
+        mut compare: impl FnMut(&mut Self, &str, &'a T, &'a T),
+    ) {
+        for i in 0..before.len().max(after.len()) {
+            let path = format!("{path}/{name}[{i}]");
+
+            match (before.get(i), after.get(i)) {
+                (Some(a), Some(b)) => compare(self, &path, a, b),
+                (Some(_), None) => self.push(&path, ChangeKind::Removed),
+                (None, Some(_)) => self.push(&path, ChangeKind::Added),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn fields(&mut self, path: &str, before: &[Field], after: &[Field]) {
+        for i in 0..before.len().max(after.len()) {
+            let path = format!("{path}/field[{i}]");
+
+            match (before.get(i), after.get(i)) {
+                (Some(a), Some(b)) if a != b => self.push(&path, ChangeKind::Changed {
+                    before: a.to_string(),
+                    after: b.to_string(),
+                }),
+                (Some(_), None) => self.push(&path, ChangeKind::Removed),
+                (None, Some(_)) => self.push(&path, ChangeKind::Added),
+                _ => {},
+            }
+        }
+    }
+
+    /// Compares plain words by index.
+    fn words(&mut self, name: &str, before: &'a [u32], after: &'a [u32]) {
+        self.runs("", name, before, after, |d, path, a, b| d.value(path, a, b));
+    }
+
+    fn state_machines(&mut self) {
+        let (before, after) = (self.before, self.after);
+
+        self.runs("", "state_machine", &before.state_machines, &after.state_machines, |d, path, a, b| {
+            members!(d, path, a, b, [unk00, state_count]);
+        });
+    }
+
+    fn states(&mut self) {
+        let (before, after) = (self.before, self.after);
+
+        self.runs("", "state", &before.states, &after.states, |d, path, a, b| {
+            members!(d, path, a, b, [unk00]);
+
+            let a_transitions = span(&before.transitions, a.transitions, a.transition_count);
+            let b_transitions = span(&after.transitions, b.transitions, b.transition_count);
+            d.runs(path, "transition", a_transitions, b_transitions, |d, path, a, b| {
+                members!(d, path, a, b, [
+                    operator, unk02, unk03, target_state, unk08, unk0c, left_kind, unk12, unk13,
+                    unk14, unk18, unk1c,
+                ]);
+
+                d.fields(
+                    &format!("{path}/left"),
+                    span(&before.fields, a.left, 1),
+                    span(&after.fields, b.left, 1),
+                );
+                d.fields(
+                    &format!("{path}/right"),
+                    span(&before.fields, a.right, 1),
+                    span(&after.fields, b.right, 1),
+                );
+            });
+        });
+    }
+
+    /// Compares the nodes at index `a` and `b`. A node that was already
+    /// compared, because it's linked to from more than one place or links
+    /// back to one of its parents, isn't descended into again.
+    fn node(&mut self, path: &str, a: usize, b: usize) {
+        let (before, after) = (self.before, self.after);
+
+        match (self.visited_before.insert(a), self.visited_after.insert(b)) {
+            (true, true) => {},
+            (false, false) => return,
+            // Only one of the sides has been here before, so the link itself
+            // changed
+            (first_before, _) => {
+                match first_before {
+                    true => self.visit(a, Side::Before),
+                    false => self.visit(b, Side::After),
+                }
+
+                self.push(path, ChangeKind::Changed {
+                    before: format!("node[{a}]"),
+                    after: format!("node[{b}]"),
+                });
+                return;
+            },
+        }
+
+        let (a, b) = (&before.nodes[a], &after.nodes[b]);
+        members!(self, path, a, b, [kind, unk02, unk03, unk04, unk14]);
+
+        self.runs(
+            path,
+            "effect",
+            span(&before.effects, a.effects, a.effect_count),
+            span(&after.effects, b.effects, b.effect_count),
+            Self::effect,
+        );
+        self.runs(
+            path,
+            "action",
+            span(&before.actions, a.actions, a.action_count),
+            span(&after.actions, b.actions, b.action_count),
+            Self::action,
+        );
+        let a_children = children(before, a.nodes, a.node_count);
+        let b_children = children(after, b.nodes, b.node_count);
+        for i in 0..a_children.len().max(b_children.len()) {
+            let path = format!("{path}/node[{i}]");
+
+            match (a_children.get(i), b_children.get(i)) {
+                (Some(&a), Some(&b)) => self.node(&path, a, b),
+                (Some(&a), None) => self.removed_node(&path, a),
+                (None, Some(&b)) => self.added_node(&path, b),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn removed_node(&mut self, path: &str, index: usize) {
+        self.visit(index, Side::Before);
+        self.push(path, ChangeKind::Removed);
+    }
+
+    fn added_node(&mut self, path: &str, index: usize) {
+        self.visit(index, Side::After);
+        self.push(path, ChangeKind::Added);
+    }
+
+    /// Marks a node and everything below it as visited without comparing
+    /// anything, for subtrees that only one of the sides has.
+    fn visit(&mut self, index: usize, side: Side) {
+        let (fxr, visited) = match side {
+            Side::Before => (self.before, &mut self.visited_before),
+            Side::After => (self.after, &mut self.visited_after),
+        };
+
+        let mut pending = vec![index];
+        while let Some(index) = pending.pop() {
+            visited.insert(index);
+
+            if let Some(node) = fxr.nodes.get(index) {
+                pending.extend(children(fxr, node.nodes, node.node_count)
+                    .into_iter()
+                    .filter(|child| !visited.contains(child)));
+            }
+        }
+    }
+
+    /// Compares the nodes that weren't reached from the root, paired up in
+    /// the order they appear in.
+    fn unreachable_nodes(&mut self) {
+        let (before, after) = (self.before, self.after);
+
+        for k in 0.. {
+            let a = (0..before.nodes.len()).find(|i| !self.visited_before.contains(i));
+            let b = (0..after.nodes.len()).find(|i| !self.visited_after.contains(i));
+            let path = format!("/unreachable[{k}]");
+
+            match (a, b) {
+                (Some(a), Some(b)) => self.node(&path, a, b),
+                (Some(a), None) => self.removed_node(&path, a),
+                (None, Some(b)) => self.added_node(&path, b),
+                (None, None) => break,
+            }
+        }
+    }
+
+    fn effect(&mut self, path: &str, a: &'a Effect, b: &'a Effect) {
+        let (before, after) = (self.before, self.after);
+        members!(self, path, a, b, [kind, unk02, unk03, unk04, unk08, unk0c, unk10, unk18, unk1c, unk28]);
+
+        self.runs(
+            path,
+            "action",
+            span(&before.actions, a.actions, a.action_count),
+            span(&after.actions, b.actions, b.action_count),
+            Self::action,
+        );
+    }
+
+    fn action(&mut self, path: &str, a: &'a Action, b: &'a Action) {
+        let (before, after) = (self.before, self.after);
+
+        // The action type decides what everything below means, comparing
+        // the rest is noise if it changed
+        if a.kind != b.kind {
+            self.value(&format!("{path}.kind"), &a.kind, &b.kind);
+            return;
+        }
+
+        members!(self, path, a, b, [
+            unk02, unk03, unk04, fields1_count, fields2_count, properties1_count,
+            properties2_count, unk18, unk38,
+        ]);
+
+        self.fields(
+            path,
+            span(&before.fields, a.fields, a.field_count()),
+            span(&after.fields, b.fields, b.field_count()),
+        );
+
+        self.runs(
+            path,
+            "field_list",
+            span(&before.field_lists, a.field_lists, a.field_list_count),
+            span(&after.field_lists, b.field_lists, b.field_list_count),
+            |d, path, a, b| {
+                d.fields(
+                    path,
+                    span(&before.fields, a.fields, a.field_count),
+                    span(&after.fields, b.fields, b.field_count),
+                );
+            },
+        );
+
+        self.runs(
+            path,
+            "property",
+            span(&before.properties, a.properties, a.property_count()),
+            span(&after.properties, b.properties, b.property_count()),
+            Self::property,
+        );
+    }
+
+    fn property(&mut self, path: &str, a: &'a Property, b: &'a Property) {
+        let (before, after) = (self.before, self.after);
+        members!(self, path, a, b, [kind, unk02, unk03, unk04, unk0c, unk24]);
+
+        let path = format!("{path} ({})", describe_properties(a.kind, b.kind));
+        self.fields(
+            &path,
+            span(&before.fields, a.fields, a.field_count),
+            span(&after.fields, b.fields, b.field_count),
+        );
+
+        self.runs(
+            &path,
+            "modifier",
+            span(&before.modifiers, a.modifiers, a.modifier_count),
+            span(&after.modifiers, b.modifiers, b.modifier_count),
+            Self::modifier,
+        );
+    }
+
+    fn modifier(&mut self, path: &str, a: &'a Modifier, b: &'a Modifier) {
+        let (before, after) = (self.before, self.after);
+        members!(self, path, a, b, [kind, unk02, unk03, unk04]);
+
+        self.fields(
+            path,
+            span(&before.fields, a.fields, a.field_count),
+            span(&after.fields, b.fields, b.field_count),
+        );
+
+        self.runs(
+            path,
+            "property",
+            span(&before.modifier_properties, a.properties, a.property_count),
+            span(&after.modifier_properties, b.properties, b.property_count),
+            Self::modifier_property,
+        );
+    }
+
+    fn modifier_property(&mut self, path: &str, a: &'a ModifierProperty, b: &'a ModifierProperty) {
+        let (before, after) = (self.before, self.after);
+        members!(self, path, a, b, [kind, unk02, unk03, unk04, unk0c]);
+
+        let path = format!("{path} ({})", describe_properties(a.kind, b.kind));
+        self.fields(
+            &path,
+            span(&before.fields, a.fields, a.field_count),
+            span(&after.fields, b.fields, b.field_count),
+        );
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Before,
+    After,
+}
+
+/// Indices of the nodes a node links to, leaving out the ones past the end of
+/// the section.
+fn children(fxr: &Fxr, link: Link, count: u32) -> Vec<usize> {
+    let Some(start) = link else {
+        return Vec::new();
+    };
+
+    (start as usize..start as usize + count as usize)
+        .take_while(|i| *i < fxr.nodes.len())
+        .collect()
+}
+
+/// Describes the properties on both sides, or just the one if they're the
+/// same kind.
+fn describe_properties(a: u16, b: u16) -> String {
+    match a == b {
+        true => describe_property(a),
+        false => format!("{} -> {}", describe_property(a), describe_property(b)),
+    }
+}
+
+/// Names the value type and function of a property so changed fields can be
+/// told apart as colors, keyframes and so on.
+fn describe_property(kind: u16) -> String {
+    let value = match kind & 0xf {
+        0 => "scalar",
+        1 => "vec2",
+        2 => "vec3",
+        3 => "color",
+        _ => "unknown",
+    };
+
+    let function = match kind >> 4 {
+        0 => "zero",
+        1 => "one",
+        2 => "constant",
+        3 => "stepped keyframes",
+        4 => "linear keyframes",
+        5 => "curve keyframes",
+        6 => "bezier keyframes",
+        7 => "random",
+        _ => "unknown function",
+    };
+
+    format!("{value}, {function}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Fxr {
+        Fxr::read(include_bytes!("../tests/fixtures/aligned.fxr")).unwrap()
+    }
+
+    fn paths(before: &Fxr, after: &Fxr) -> Vec<String> {
+        diff(before, after).into_iter().map(|c| c.to_string()).collect()
+    }
+
+    /// Gives the fixture's child node a child of its own.
+    fn link_child(fxr: &mut Fxr, child: u32) {
+        fxr.nodes[1].nodes = Some(child);
+        fxr.nodes[1].node_count = 1;
+    }
+
+    #[test]
+    fn identical_fxrs_have_no_changes() {
+        assert!(diff(&fixture(), &fixture()).is_empty());
+    }
+
+    #[test]
+    fn nodes_linking_to_themselves_are_compared_once() {
+        let mut before = fixture();
+        link_child(&mut before, 1);
+        let after = before.clone();
+
+        assert!(diff(&before, &after).is_empty());
+
+        let mut after = after;
+        after.nodes[1].unk04 = 1;
+        assert_eq!(paths(&before, &after), ["/root/node[0].unk04: 0 -> 1"]);
+    }
+
+    #[test]
+    fn links_back_to_a_parent_are_reported() {
+        let mut before = fixture();
+        before.nodes.push(before.nodes[1].clone());
+        let mut after = before.clone();
+
+        link_child(&mut before, 2);
+        link_child(&mut after, 0);
+
+        // The node that's no longer linked shows up as unreachable
+        assert_eq!(paths(&before, &after), [
+            "/root/node[0]/node[0]: node[2] -> node[0]",
+            "/unreachable[0]: added",
+        ]);
+    }
+
+    #[test]
+    fn unreachable_nodes_are_paired_up() {
+        let mut before = fixture();
+        before.nodes.push(before.nodes[1].clone());
+        let mut after = before.clone();
+        after.nodes[2].kind = 2002;
+        after.nodes.push(after.nodes[1].clone());
+
+        assert_eq!(paths(&before, &after), [
+            "/unreachable[0].kind: 2001 -> 2002",
+            "/unreachable[1]: added",
+        ]);
+    }
+
+    #[test]
+    fn reports_the_header_state_machines_and_later_sections() {
+        let before = fixture();
+        let mut after = before.clone();
+        after.unk04 = 0;
+        after.unk8c = 1;
+        after.state_machines[0].unk00 = 5;
+        after.section13[0] = 14;
+        after.section14.push(1);
+
+        assert_eq!(paths(&before, &after), [
+            "/unk04: 4660 -> 0",
+            "/unk8c: 140 -> 1",
+            "/state_machine[0].unk00: 0 -> 5",
+            "/section13[0]: 13 -> 14",
+            "/section14[0]: added",
+        ]);
+    }
+
+    #[test]
+    fn changed_property_kinds_describe_both_sides() {
+        let mut before = fixture();
+        before.properties[0].kind = 0x20;
+        let mut after = before.clone();
+        after.properties[0].kind = 0x23;
+        after.fields[4] = Field::from_int(1);
+
+        assert_eq!(paths(&before, &after), [
+            "/root/node[0]/effect[0]/action[0]/property[0].kind: 32 -> 35",
+            "/root/node[0]/effect[0]/action[0]/property[0] (scalar, constant -> color, constant)/field[0]: 0.25 -> 1",
+        ]);
+    }
+}
@@ -0,0 +1,422 @@
+use serde::{Deserialize, Serialize};
+
+use crate::header::{Header, SectionEntry, Version, MAGIC};
+use crate::model::{
+    Action, Effect, Field, FieldList, Links, Modifier, ModifierProperty, Node, Property, Record,
+    Section, State, StateMachine, Transition,
+};
+use crate::reader::Reader;
+use crate::references::{reference_fields, ReferenceKind};
+use crate::writer::Writer;
+use crate::ReadError;
+
+/// A parsed FXR. Reading a file and writing it back out without touching it
+/// yields the exact same bytes, anything that isn't part of a section is
+/// carried over as is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fxr {
+    pub version: Version,
+    pub id: u32,
+    pub unk04: u16,
+    pub unk08: u32,
+    pub unk68: u32,
+    pub unk6c: u32,
+    /// Only present in version 5 FXRs.
+    pub unk88: u32,
+    pub unk8c: u32,
+    #[serde(default)]
+    pub state_machines: Vec<StateMachine>,
+    #[serde(default)]
+    pub states: Vec<State>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default)]
+    pub modifier_properties: Vec<ModifierProperty>,
+    #[serde(default)]
+    pub field_lists: Vec<FieldList>,
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    #[serde(default)]
+    pub section12: Vec<u32>,
+    #[serde(default)]
+    pub section13: Vec<u32>,
+    #[serde(default)]
+    pub section14: Vec<u32>,
+    #[serde(default)]
+    layout: Layout,
+}
+
+/// Where the sections sat in the file we read, so they can be written back to
+/// the same place.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Layout {
+    /// Bytes between the header and the first section.
+    #[serde(with = "crate::hex")]
+    after_header: Vec<u8>,
+    /// Sections in file order. Sections that were null aren't in here.
+    #[serde(default)]
+    placements: Vec<Placement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Placement {
+    section: Section,
+    /// The section didn't start on its usual alignment. It's written right
+    /// after whatever precedes it instead of being padded, which keeps files
+    /// like that byte identical.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unaligned: bool,
+    /// Padding, or data we don't understand, between the end of this section
+    /// and the start of the next.
+    #[serde(with = "crate::hex")]
+    trailing: Vec<u8>,
+}
+
+impl Fxr {
+    pub fn read(bytes: &[u8]) -> Result<Self, ReadError> {
+        Self::read_with(bytes, Links::new)
+    }
+
+    /// Reads an FXR the game has loaded at `base`. Loading turns the offsets
+    /// in the records into pointers, these are turned back into offsets.
+    pub fn read_relocated(bytes: &[u8], base: u64) -> Result<Self, ReadError> {
+        Self::read_with(bytes, |offsets| Links::relocated(offsets, base))
+    }
+
+    fn read_with(bytes: &[u8], links: impl FnOnce([u32; 14]) -> Links) -> Result<Self, ReadError> {
+        let header = Header::read(bytes)?;
+        let reader = Reader::new(bytes);
+
+        let entries: [SectionEntry; 14] = std::array::from_fn(|i| {
+            header.sections.get(i).copied().unwrap_or_default()
+        });
+        let links = links(entries.map(|e| e.offset));
+
+        let v5 = header.version == Version::V5;
+
+        Ok(Self {
+            version: header.version,
+            id: header.id,
+            unk04: reader.u16(0x4)?,
+            unk08: reader.u32(0x8)?,
+            unk68: reader.u32(0x68)?,
+            unk6c: reader.u32(0x6c)?,
+            unk88: if v5 { reader.u32(0x88)? } else { 0 },
+            unk8c: if v5 { reader.u32(0x8c)? } else { 0 },
+            state_machines: read_section(&reader, &entries, Section::StateMachines, &links)?,
+            states: read_section(&reader, &entries, Section::States, &links)?,
+            transitions: read_section(&reader, &entries, Section::Transitions, &links)?,
+            nodes: read_section(&reader, &entries, Section::Nodes, &links)?,
+            effects: read_section(&reader, &entries, Section::Effects, &links)?,
+            actions: read_section(&reader, &entries, Section::Actions, &links)?,
+            properties: read_section(&reader, &entries, Section::Properties, &links)?,
+            modifiers: read_section(&reader, &entries, Section::Modifiers, &links)?,
+            modifier_properties: read_section(&reader, &entries, Section::ModifierProperties, &links)?,
+            field_lists: read_section(&reader, &entries, Section::FieldLists, &links)?,
+            fields: read_section(&reader, &entries, Section::Fields, &links)?,
+            section12: read_words(&reader, &entries, Section::Section12)?,
+            section13: read_words(&reader, &entries, Section::Section13)?,
+            section14: read_words(&reader, &entries, Section::Section14)?,
+            layout: Layout::read(&reader, &entries, header.version)?,
+        })
+    }
+
+    /// Moves the FXR to another ID. References the FXR makes to itself are
+    /// moved along with it.
+    pub fn reid(&mut self, id: u32) {
+        let previous = self.id;
+
+        let own_references = reference_fields(self)
+            .filter(|(field, kind, _)| {
+                *kind == ReferenceKind::Fxr && self.fields[*field].as_int() == previous as i32
+            })
+            .map(|(field, _, _)| field)
+            .collect::<Vec<_>>();
+
+        for field in own_references {
+            self.fields[field] = Field::from_int(id as i32);
+        }
+
+        self.id = id;
+    }
+
+    /// The root of the node tree.
+    pub fn root(&self) -> Option<&Node> {
+        self.nodes.first()
+    }
+
+    /// Record count for a section, as it'll end up in the header.
+    pub fn section_len(&self, section: Section) -> usize {
+        match section {
+            Section::StateMachines => self.state_machines.len(),
+            Section::States => self.states.len(),
+            Section::Transitions => self.transitions.len(),
+            Section::Nodes => self.nodes.len(),
+            Section::Effects => self.effects.len(),
+            Section::Actions => self.actions.len(),
+            Section::Properties => self.properties.len(),
+            Section::Modifiers => self.modifiers.len(),
+            Section::ModifierProperties => self.modifier_properties.len(),
+            Section::FieldLists => self.field_lists.len(),
+            Section::Fields => self.fields.len(),
+            Section::Section12 => self.section12.len(),
+            Section::Section13 => self.section13.len(),
+            Section::Section14 => self.section14.len(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let offsets = self.section_offsets();
+        let links = Links::new(offsets);
+
+        let mut writer = Writer::default();
+        self.write_header(&mut writer, &offsets);
+        writer.bytes(&self.layout.after_header);
+
+        for placement in &self.layout.placements {
+            if !placement.unaligned {
+                writer.align(alignment(placement.section));
+            }
+
+            self.write_section(&mut writer, placement.section, &links);
+            writer.bytes(&placement.trailing);
+        }
+
+        for section in self.unplaced_sections() {
+            writer.align(alignment(section));
+            self.write_section(&mut writer, section, &links);
+        }
+
+        writer.finish()
+    }
+
+    fn write_header(&self, writer: &mut Writer, offsets: &[u32; 14]) {
+        writer.bytes(MAGIC);
+        writer.u16(self.unk04);
+        writer.u16(self.version.raw());
+        writer.u32(self.unk08);
+        writer.u32(self.id);
+
+        let write_entry = |writer: &mut Writer, section: Section| {
+            writer.u32(offsets[section as usize]);
+            writer.u32(self.section_len(section) as u32);
+        };
+
+        for section in &Section::ALL[..11] {
+            write_entry(writer, *section);
+        }
+
+        writer.u32(self.unk68);
+        writer.u32(self.unk6c);
+
+        if self.version == Version::V5 {
+            for section in &Section::ALL[11..] {
+                write_entry(writer, *section);
+            }
+
+            writer.u32(self.unk88);
+            writer.u32(self.unk8c);
+        }
+    }
+
+    fn write_section(&self, writer: &mut Writer, section: Section, links: &Links) {
+        match section {
+            Section::StateMachines => write_records(writer, &self.state_machines, links),
+            Section::States => write_records(writer, &self.states, links),
+            Section::Transitions => write_records(writer, &self.transitions, links),
+            Section::Nodes => write_records(writer, &self.nodes, links),
+            Section::Effects => write_records(writer, &self.effects, links),
+            Section::Actions => write_records(writer, &self.actions, links),
+            Section::Properties => write_records(writer, &self.properties, links),
+            Section::Modifiers => write_records(writer, &self.modifiers, links),
+            Section::ModifierProperties => write_records(writer, &self.modifier_properties, links),
+            Section::FieldLists => write_records(writer, &self.field_lists, links),
+            Section::Fields => write_records(writer, &self.fields, links),
+            Section::Section12 => self.section12.iter().for_each(|w| writer.u32(*w)),
+            Section::Section13 => self.section13.iter().for_each(|w| writer.u32(*w)),
+            Section::Section14 => self.section14.iter().for_each(|w| writer.u32(*w)),
+        }
+    }
+
+    /// Sections that weren't in the file we read but have records now. These
+    /// go after everything else.
+    fn unplaced_sections(&self) -> impl Iterator<Item = Section> + '_ {
+        self.sections()
+            .filter(|s| self.section_len(*s) != 0)
+            .filter(|s| !self.layout.placements.iter().any(|p| p.section == *s))
+    }
+
+    /// Sections this FXR's version has.
+    fn sections(&self) -> impl Iterator<Item = Section> {
+        Section::ALL.into_iter().take(self.version.section_count())
+    }
+
+    /// Works out where every section will end up, mirroring `to_bytes`.
+    fn section_offsets(&self) -> [u32; 14] {
+        let mut offsets = [0; 14];
+        let mut position = self.version.header_size() + self.layout.after_header.len();
+
+        let mut place = |section: Section, aligned: bool, trailing: usize| {
+            if aligned {
+                position = position.next_multiple_of(alignment(section));
+            }
+
+            offsets[section as usize] = position as u32;
+            position += self.section_len(section) * section.record_size() + trailing;
+        };
+
+        for placement in &self.layout.placements {
+            place(placement.section, !placement.unaligned, placement.trailing.len());
+        }
+
+        for section in self.unplaced_sections() {
+            place(section, true, 0);
+        }
+
+        offsets
+    }
+}
+
+impl Layout {
+    fn read(reader: &Reader, entries: &[SectionEntry; 14], version: Version) -> Result<Self, ReadError> {
+        let mut placed = Section::ALL.into_iter()
+            .take(version.section_count())
+            .filter(|s| entries[*s as usize].offset != 0)
+            .collect::<Vec<_>>();
+
+        // Empty sections can share their offset with the section after them
+        placed.sort_by_key(|s| (entries[*s as usize].offset, entries[*s as usize].count != 0));
+
+        let mut layout = Self::default();
+        let mut position = version.header_size();
+
+        for section in placed {
+            let entry = entries[section as usize];
+            let start = entry.offset as usize;
+            if start < position {
+                return Err(ReadError::OverlappingSection(section.number()));
+            }
+
+            let gap = reader.bytes(position, start - position)?.to_vec();
+            match layout.placements.last_mut() {
+                Some(previous) => previous.trailing = gap,
+                None => layout.after_header = gap,
+            }
+
+            layout.placements.push(Placement {
+                section,
+                unaligned: !start.is_multiple_of(alignment(section)),
+                trailing: Vec::new(),
+            });
+            position = start + entry.count as usize * section.record_size();
+        }
+
+        let rest = reader.bytes(position, reader.len().saturating_sub(position))?.to_vec();
+        match layout.placements.last_mut() {
+            Some(last) => last.trailing = rest,
+            None => layout.after_header = rest,
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Sections holding pointers stay pointer aligned.
+fn alignment(section: Section) -> usize {
+    match section.record_size() {
+        Field::SIZE => 0x4,
+        _ => 0x8,
+    }
+}
+
+fn read_section<T: Record>(
+    reader: &Reader,
+    entries: &[SectionEntry; 14],
+    section: Section,
+    links: &Links,
+) -> Result<Vec<T>, ReadError> {
+    let entry = entries[section as usize];
+    if entry.offset == 0 && entry.count != 0 {
+        return Err(ReadError::MissingSection(section.number()));
+    }
+
+    (0..entry.count as usize)
+        .map(|i| T::read(reader, entry.offset as usize + i * T::SIZE, links))
+        .collect()
+}
+
+fn read_words(
+    reader: &Reader,
+    entries: &[SectionEntry; 14],
+    section: Section,
+) -> Result<Vec<u32>, ReadError> {
+    let entry = entries[section as usize];
+    if entry.offset == 0 && entry.count != 0 {
+        return Err(ReadError::MissingSection(section.number()));
+    }
+
+    (0..entry.count as usize)
+        .map(|i| reader.u32(entry.offset as usize + i * 0x4))
+        .collect()
+}
+
+fn write_records<T: Record>(writer: &mut Writer, records: &[T], links: &Links) {
+    for record in records {
+        record.write(writer, links);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fixture with two SFX reference actions, one spawning the FXR
+    /// itself and one spawning FXR 2000.
+    fn spawner() -> Fxr {
+        let mut fxr = Fxr::read(include_bytes!("../tests/fixtures/aligned.fxr")).unwrap();
+
+        let mut other = fxr.actions[0].clone();
+        other.kind = 129;
+        other.fields = Some(fxr.fields.len() as u32);
+        fxr.fields.extend([Field::from_int(2000), Field(0)]);
+
+        fxr.actions[0].kind = 129;
+        let own = fxr.actions[0].fields.unwrap() as usize;
+        fxr.fields[own] = Field::from_int(fxr.id as i32);
+        fxr.actions.push(other);
+
+        fxr
+    }
+
+    #[test]
+    fn reid_moves_references_to_itself_along() {
+        let mut fxr = spawner();
+        let own = fxr.actions[0].fields.unwrap() as usize;
+        fxr.reid(1500);
+
+        assert_eq!(fxr.id, 1500);
+        assert_eq!(fxr.fields[own], Field::from_int(1500));
+    }
+
+    #[test]
+    fn reid_leaves_other_references_alone() {
+        let mut fxr = spawner();
+        let before = fxr.fields.clone();
+        fxr.reid(1500);
+
+        let changed = (0..before.len()).filter(|i| before[*i] != fxr.fields[*i]).count();
+        assert_eq!(changed, 1);
+        assert_eq!(fxr.fields[before.len() - 2], Field::from_int(2000));
+        assert_eq!(crate::child_fxrs(&fxr), [1500, 2000]);
+    }
+}
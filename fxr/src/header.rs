@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::Section;
+use crate::reader::Reader;
+use crate::ReadError;
+
+pub(crate) const MAGIC: &[u8; 4] = b"FXR\0";
+
+/// Size of the header for version 4 FXRs. Version 5 adds three more sections.
+const HEADER_SIZE_V4: usize = 0x70;
+const HEADER_SIZE_V5: usize = 0x90;
+
+/// Size of a single action record in section 6.
+const ACTION_SIZE: usize = 0x40;
+
+/// Actions that only exist from Elden Ring onwards.
+const ELDEN_RING_ACTIONS: [u16; 5] = [
+    10013, // Water interaction
+    10014, // Lens flare
+    10015, // Rich model
+    10500, // Level of detail thresholds
+    11000, // Spot light
+];
+
+/// The FXR format version, stored as a u16 at 0x6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Version {
+    /// Used by Dark Souls 3.
+    #[serde(alias = "DarkSouls3")]
+    V4,
+    /// Introduced with Sekiro and kept around for Elden Ring and Armored
+    /// Core 6.
+    #[serde(alias = "Sekiro")]
+    V5,
+}
+
+impl Version {
+    pub fn from_raw(raw: u16) -> Option<Self> {
+        match raw {
+            4 => Some(Self::V4),
+            5 => Some(Self::V5),
+            _ => None,
+        }
+    }
+
+    pub fn raw(&self) -> u16 {
+        match self {
+            Self::V4 => 4,
+            Self::V5 => 5,
+        }
+    }
+
+    /// Amount of sections described in the header.
+    pub fn section_count(&self) -> usize {
+        match self {
+            Self::V4 => 11,
+            Self::V5 => 14,
+        }
+    }
+
+    pub fn header_size(&self) -> usize {
+        match self {
+            Self::V4 => HEADER_SIZE_V4,
+            Self::V5 => HEADER_SIZE_V5,
+        }
+    }
+}
+
+/// The games that an FXR can be loaded by, judging by its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    /// Version 4 FXRs. Only Dark Souls 3 loads these.
+    DarkSouls3,
+    /// Version 5 FXRs that don't use anything introduced after Sekiro, which
+    /// are taken to be made for Sekiro.
+    Sekiro,
+    /// Version 5 FXRs using actions introduced with Elden Ring. Armored Core 6
+    /// shares these.
+    EldenRing,
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::DarkSouls3 => "Dark Souls 3",
+            Self::Sekiro => "Sekiro",
+            Self::EldenRing => "Elden Ring",
+        })
+    }
+}
+
+/// Offset and record count of one of the header's sections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SectionEntry {
+    pub offset: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    pub id: u32,
+    /// Sections 1 through 11, or 14 for version 5 FXRs.
+    pub sections: Vec<SectionEntry>,
+}
+
+impl Header {
+    pub fn read(bytes: &[u8]) -> Result<Self, ReadError> {
+        let reader = Reader::new(bytes);
+
+        if reader.bytes(0x0, 0x4)? != MAGIC {
+            return Err(ReadError::Magic);
+        }
+
+        let raw_version = reader.u16(0x6)?;
+        let version = Version::from_raw(raw_version)
+            .ok_or(ReadError::UnsupportedVersion(raw_version))?;
+
+        // Sections 1 through 11 sit right after the ID, the ones added with
+        // version 5 come after a pair of unknown u32s.
+        let sections = (0..version.section_count())
+            .map(|i| {
+                let entry = match i {
+                    0..=10 => 0x10 + i * 0x8,
+                    _ => 0x70 + (i - 11) * 0x8,
+                };
+
+                Ok(SectionEntry {
+                    offset: reader.u32(entry)?,
+                    count: reader.u32(entry + 0x4)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ReadError>>()?;
+
+        Ok(Self {
+            version,
+            id: reader.u32(0xc)?,
+            sections,
+        })
+    }
+
+    /// Section by its number as used in the format documentation, starting
+    /// at 1.
+    pub fn section(&self, number: usize) -> Option<&SectionEntry> {
+        self.sections.get(number.checked_sub(1)?)
+    }
+
+    /// Size of the FXR up to the end of its last section.
+    pub fn extent(&self) -> usize {
+        self.sections.iter()
+            .zip(Section::ALL)
+            .map(|(entry, section)| entry.offset as usize + entry.count as usize * section.record_size())
+            .fold(self.version.header_size(), usize::max)
+    }
+
+    /// Figures out which games can load the FXR. Version 5 FXRs don't tell
+    /// Sekiro apart from later games in the header so we look at the actions
+    /// it uses instead.
+    pub fn family(&self, bytes: &[u8]) -> Result<Family, ReadError> {
+        if self.version == Version::V4 {
+            return Ok(Family::DarkSouls3);
+        }
+
+        let reader = Reader::new(bytes);
+        let actions = self.section(6).copied().unwrap_or_default();
+
+        for i in 0..actions.count as usize {
+            let action_type = reader.u16(actions.offset as usize + i * ACTION_SIZE)?;
+            if ELDEN_RING_ACTIONS.contains(&action_type) {
+                return Ok(Family::EldenRing);
+            }
+        }
+
+        Ok(Family::Sekiro)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An FXR of the supplied version with nothing but actions of the
+    /// supplied types in section 6.
+    fn fxr(version: Version, actions: &[u16]) -> Vec<u8> {
+        let offset = version.header_size();
+        let mut bytes = vec![0; offset + actions.len() * ACTION_SIZE];
+        bytes[..4].copy_from_slice(MAGIC);
+        bytes[0x6..0x8].copy_from_slice(&version.raw().to_le_bytes());
+        bytes[0xc..0x10].copy_from_slice(&300u32.to_le_bytes());
+
+        // Section 6 entry
+        bytes[0x38..0x3c].copy_from_slice(&(offset as u32).to_le_bytes());
+        bytes[0x3c..0x40].copy_from_slice(&(actions.len() as u32).to_le_bytes());
+
+        for (i, action) in actions.iter().enumerate() {
+            let at = offset + i * ACTION_SIZE;
+            bytes[at..at + 2].copy_from_slice(&action.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn reads_both_versions() {
+        let v4 = Header::read(&fxr(Version::V4, &[])).unwrap();
+        assert_eq!(v4.version, Version::V4);
+        assert_eq!(v4.id, 300);
+        assert_eq!(v4.sections.len(), 11);
+
+        let v5 = Header::read(&fxr(Version::V5, &[])).unwrap();
+        assert_eq!(v5.version, Version::V5);
+        assert_eq!(v5.sections.len(), 14);
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let mut bytes = fxr(Version::V5, &[]);
+        bytes[0x6..0x8].copy_from_slice(&6u16.to_le_bytes());
+
+        assert!(matches!(Header::read(&bytes), Err(ReadError::UnsupportedVersion(6))));
+    }
+
+    #[test]
+    fn tells_families_apart_by_their_actions() {
+        let family = |version, actions: &[u16]| {
+            let bytes = fxr(version, actions);
+            Header::read(&bytes).unwrap().family(&bytes).unwrap()
+        };
+
+        assert_eq!(family(Version::V4, &[10014]), Family::DarkSouls3);
+        assert_eq!(family(Version::V5, &[]), Family::Sekiro);
+        assert_eq!(family(Version::V5, &[600, 601]), Family::Sekiro);
+        assert_eq!(family(Version::V5, &[600, 10014]), Family::EldenRing);
+    }
+
+    #[test]
+    fn versions_decode_by_their_old_names() {
+        assert_eq!(serde_json::from_str::<Version>("\"Sekiro\"").unwrap(), Version::V5);
+        assert_eq!(serde_json::from_str::<Version>("\"DarkSouls3\"").unwrap(), Version::V4);
+        assert_eq!(serde_json::to_string(&Version::V5).unwrap(), "\"V5\"");
+    }
+}
@@ -0,0 +1,29 @@
+//! Keeps raw bytes readable when an FXR is turned into text.
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    let hex = bytes.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+
+    serializer.serialize_str(&hex)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    let hex = hex.trim();
+
+    if hex.len() % 2 != 0 {
+        return Err(serde::de::Error::custom("hex string has an odd length"));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid hex byte at {i}")))
+        })
+        .collect()
+}
@@ -0,0 +1,39 @@
+use thiserror::Error;
+
+mod archive;
+mod diff;
+mod document;
+mod header;
+mod hex;
+mod model;
+mod reader;
+mod references;
+mod text;
+mod writer;
+
+pub use archive::{pack_archive_fxrs, read_archive_fxrs, Archive, ArchiveError, Bnd4, BndFile};
+pub use diff::{diff, Change, ChangeKind};
+pub use document::Fxr;
+pub use header::{Family, Header, SectionEntry, Version};
+pub use model::{
+    span, Action, Effect, Field, FieldList, Link, Modifier, ModifierProperty, Node, Property,
+    Section, State, StateMachine, Transition,
+};
+pub use references::{child_fxrs, references, Reference, ReferenceKind};
+pub use text::{read_binary, read_file, ConvertError, Format, ReadFileError};
+
+#[derive(Debug, Error)]
+pub enum ReadError {
+    #[error("File is not an FXR.")]
+    Magic,
+    #[error("FXR version {0} is not supported.")]
+    UnsupportedVersion(u16),
+    #[error("FXR is truncated. Tried reading {1:#x} bytes at {0:#x}.")]
+    Truncated(usize, usize),
+    #[error("Section {0} has records but no offset.")]
+    MissingSection(usize),
+    #[error("Section {0} overlaps with the section before it.")]
+    OverlappingSection(usize),
+    #[error("Offset {1:#x} at {0:#x} does not point at a record.")]
+    UnresolvedPointer(usize, u64),
+}
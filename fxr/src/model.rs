@@ -0,0 +1,672 @@
+use serde::{Deserialize, Serialize};
+
+use crate::reader::Reader;
+use crate::writer::Writer;
+use crate::ReadError;
+
+/// The sections of an FXR in the order the header lists them. Every section is
+/// a contiguous array of fixed size records, the header holds its offset and
+/// record count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Section {
+    StateMachines,
+    States,
+    Transitions,
+    Nodes,
+    Effects,
+    Actions,
+    Properties,
+    Modifiers,
+    ModifierProperties,
+    FieldLists,
+    Fields,
+    /// Sections 12 through 14 only exist in version 5 FXRs and aren't
+    /// understood yet, their records are kept as plain words.
+    Section12,
+    Section13,
+    Section14,
+}
+
+impl Section {
+    pub const ALL: [Section; 14] = [
+        Self::StateMachines,
+        Self::States,
+        Self::Transitions,
+        Self::Nodes,
+        Self::Effects,
+        Self::Actions,
+        Self::Properties,
+        Self::Modifiers,
+        Self::ModifierProperties,
+        Self::FieldLists,
+        Self::Fields,
+        Self::Section12,
+        Self::Section13,
+        Self::Section14,
+    ];
+
+    /// Number as used in the format documentation, starting at 1.
+    pub fn number(&self) -> usize {
+        *self as usize + 1
+    }
+
+    pub fn record_size(&self) -> usize {
+        match self {
+            Self::StateMachines => StateMachine::SIZE,
+            Self::States => State::SIZE,
+            Self::Transitions => Transition::SIZE,
+            Self::Nodes => Node::SIZE,
+            Self::Effects => Effect::SIZE,
+            Self::Actions => Action::SIZE,
+            Self::Properties => Property::SIZE,
+            Self::Modifiers => Modifier::SIZE,
+            Self::ModifierProperties => ModifierProperty::SIZE,
+            Self::FieldLists => FieldList::SIZE,
+            Self::Fields | Self::Section12 | Self::Section13 | Self::Section14 => Field::SIZE,
+        }
+    }
+}
+
+/// Reference to the first of a run of records in another section. The game
+/// relocates these into pointers when it loads the FXR, on disk they're
+/// offsets from the start of the file.
+pub type Link = Option<u32>;
+
+/// Translates between on-disk offsets and record indices.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Links {
+    offsets: [u32; 14],
+    /// Address the FXR was loaded at if it was relocated, zero for files.
+    base: u64,
+}
+
+impl Links {
+    pub fn new(offsets: [u32; 14]) -> Self {
+        Self { offsets, base: 0 }
+    }
+
+    pub fn relocated(offsets: [u32; 14], base: u64) -> Self {
+        Self { offsets, base }
+    }
+
+    /// Resolves the offset stored at `slot` into an index into `section`.
+    pub fn read(&self, reader: &Reader, slot: usize, section: Section) -> Result<Link, ReadError> {
+        let pointer = reader.u64(slot)?;
+        if pointer == 0 {
+            return Ok(None);
+        }
+
+        let start = self.base + self.offsets[section as usize] as u64;
+        let size = section.record_size() as u64;
+
+        pointer.checked_sub(start)
+            .filter(|relative| relative % size == 0)
+            .and_then(|relative| u32::try_from(relative / size).ok())
+            .map(Some)
+            .ok_or(ReadError::UnresolvedPointer(slot, pointer))
+    }
+
+    pub fn write(&self, writer: &mut Writer, link: Link, section: Section) {
+        let pointer = link.map_or(0, |index| {
+            self.offsets[section as usize] as u64 + index as u64 * section.record_size() as u64
+        });
+
+        writer.u64(pointer);
+    }
+}
+
+/// Records that make up the sections of an FXR.
+pub(crate) trait Record: Sized {
+    const SIZE: usize;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError>;
+
+    fn write(&self, writer: &mut Writer, links: &Links);
+}
+
+/// Records pointed at by `link`, or nothing if it's null.
+pub fn span<T>(records: &[T], link: Link, count: u32) -> &[T] {
+    link.and_then(|start| {
+        let start = start as usize;
+        records.get(start..start.checked_add(count as usize)?)
+    })
+    .unwrap_or(&[])
+}
+
+/// Section 1. There's only ever one of these, it holds the states the effect
+/// can be in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateMachine {
+    pub unk00: u32,
+    pub state_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub states: Link,
+}
+
+impl Record for StateMachine {
+    const SIZE: usize = 0x10;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            unk00: reader.u32(offset)?,
+            state_count: reader.u32(offset + 0x4)?,
+            states: links.read(reader, offset + 0x8, Section::States)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u32(self.unk00);
+        writer.u32(self.state_count);
+        links.write(writer, self.states, Section::States);
+    }
+}
+
+/// Section 2.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub unk00: u32,
+    pub transition_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transitions: Link,
+}
+
+impl Record for State {
+    const SIZE: usize = 0x10;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            unk00: reader.u32(offset)?,
+            transition_count: reader.u32(offset + 0x4)?,
+            transitions: links.read(reader, offset + 0x8, Section::Transitions)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u32(self.unk00);
+        writer.u32(self.transition_count);
+        links.write(writer, self.transitions, Section::Transitions);
+    }
+}
+
+/// Section 3. Compares two fields to decide on moving to another state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub operator: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub target_state: u32,
+    pub unk08: u32,
+    pub unk0c: u32,
+    pub left_kind: u16,
+    pub unk12: u8,
+    pub unk13: u8,
+    pub unk14: u32,
+    pub unk18: u32,
+    pub unk1c: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Link,
+}
+
+impl Record for Transition {
+    const SIZE: usize = 0x30;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            operator: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            target_state: reader.u32(offset + 0x4)?,
+            unk08: reader.u32(offset + 0x8)?,
+            unk0c: reader.u32(offset + 0xc)?,
+            left_kind: reader.u16(offset + 0x10)?,
+            unk12: reader.u8(offset + 0x12)?,
+            unk13: reader.u8(offset + 0x13)?,
+            unk14: reader.u32(offset + 0x14)?,
+            unk18: reader.u32(offset + 0x18)?,
+            unk1c: reader.u32(offset + 0x1c)?,
+            left: links.read(reader, offset + 0x20, Section::Fields)?,
+            right: links.read(reader, offset + 0x28, Section::Fields)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.operator);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.target_state);
+        writer.u32(self.unk08);
+        writer.u32(self.unk0c);
+        writer.u16(self.left_kind);
+        writer.u8(self.unk12);
+        writer.u8(self.unk13);
+        writer.u32(self.unk14);
+        writer.u32(self.unk18);
+        writer.u32(self.unk1c);
+        links.write(writer, self.left, Section::Fields);
+        links.write(writer, self.right, Section::Fields);
+    }
+}
+
+/// Section 4. Nodes form the tree of the effect, the first one is the root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub effect_count: u32,
+    pub action_count: u32,
+    pub node_count: u32,
+    pub unk14: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effects: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actions: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nodes: Link,
+}
+
+impl Record for Node {
+    const SIZE: usize = 0x30;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            effect_count: reader.u32(offset + 0x8)?,
+            action_count: reader.u32(offset + 0xc)?,
+            node_count: reader.u32(offset + 0x10)?,
+            unk14: reader.u32(offset + 0x14)?,
+            effects: links.read(reader, offset + 0x18, Section::Effects)?,
+            actions: links.read(reader, offset + 0x20, Section::Actions)?,
+            nodes: links.read(reader, offset + 0x28, Section::Nodes)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.effect_count);
+        writer.u32(self.action_count);
+        writer.u32(self.node_count);
+        writer.u32(self.unk14);
+        links.write(writer, self.effects, Section::Effects);
+        links.write(writer, self.actions, Section::Actions);
+        links.write(writer, self.nodes, Section::Nodes);
+    }
+}
+
+/// Section 5. Groups the actions that together make up a single effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Effect {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub unk08: u32,
+    pub unk0c: u32,
+    pub unk10: u32,
+    pub action_count: u32,
+    pub unk18: u32,
+    pub unk1c: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actions: Link,
+    pub unk28: u64,
+}
+
+impl Record for Effect {
+    const SIZE: usize = 0x30;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            unk08: reader.u32(offset + 0x8)?,
+            unk0c: reader.u32(offset + 0xc)?,
+            unk10: reader.u32(offset + 0x10)?,
+            action_count: reader.u32(offset + 0x14)?,
+            unk18: reader.u32(offset + 0x18)?,
+            unk1c: reader.u32(offset + 0x1c)?,
+            actions: links.read(reader, offset + 0x20, Section::Actions)?,
+            unk28: reader.u64(offset + 0x28)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.unk08);
+        writer.u32(self.unk0c);
+        writer.u32(self.unk10);
+        writer.u32(self.action_count);
+        writer.u32(self.unk18);
+        writer.u32(self.unk1c);
+        links.write(writer, self.actions, Section::Actions);
+        writer.u64(self.unk28);
+    }
+}
+
+/// Section 6. The action type decides what the fields and properties mean.
+/// Both field runs and both property runs are stored back to back so each
+/// pair shares a single link.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub fields1_count: u32,
+    pub field_list_count: u32,
+    pub properties1_count: u32,
+    pub fields2_count: u32,
+    pub unk18: u32,
+    pub properties2_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_lists: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Link,
+    pub unk38: u64,
+}
+
+impl Action {
+    pub fn field_count(&self) -> u32 {
+        self.fields1_count + self.fields2_count
+    }
+
+    pub fn property_count(&self) -> u32 {
+        self.properties1_count + self.properties2_count
+    }
+}
+
+impl Record for Action {
+    const SIZE: usize = 0x40;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            fields1_count: reader.u32(offset + 0x8)?,
+            field_list_count: reader.u32(offset + 0xc)?,
+            properties1_count: reader.u32(offset + 0x10)?,
+            fields2_count: reader.u32(offset + 0x14)?,
+            unk18: reader.u32(offset + 0x18)?,
+            properties2_count: reader.u32(offset + 0x1c)?,
+            fields: links.read(reader, offset + 0x20, Section::Fields)?,
+            field_lists: links.read(reader, offset + 0x28, Section::FieldLists)?,
+            properties: links.read(reader, offset + 0x30, Section::Properties)?,
+            unk38: reader.u64(offset + 0x38)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.fields1_count);
+        writer.u32(self.field_list_count);
+        writer.u32(self.properties1_count);
+        writer.u32(self.fields2_count);
+        writer.u32(self.unk18);
+        writer.u32(self.properties2_count);
+        links.write(writer, self.fields, Section::Fields);
+        links.write(writer, self.field_lists, Section::FieldLists);
+        links.write(writer, self.properties, Section::Properties);
+        writer.u64(self.unk38);
+    }
+}
+
+/// Section 7. A value that can change over the lifetime of the effect. The
+/// kind holds the value type in the low nibble and the function (constant,
+/// stepped, linear, curve...) above it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Property {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub field_count: u32,
+    pub unk0c: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modifiers: Link,
+    pub modifier_count: u32,
+    pub unk24: u32,
+}
+
+impl Record for Property {
+    const SIZE: usize = 0x28;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            field_count: reader.u32(offset + 0x8)?,
+            unk0c: reader.u32(offset + 0xc)?,
+            fields: links.read(reader, offset + 0x10, Section::Fields)?,
+            modifiers: links.read(reader, offset + 0x18, Section::Modifiers)?,
+            modifier_count: reader.u32(offset + 0x20)?,
+            unk24: reader.u32(offset + 0x24)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.field_count);
+        writer.u32(self.unk0c);
+        links.write(writer, self.fields, Section::Fields);
+        links.write(writer, self.modifiers, Section::Modifiers);
+        writer.u32(self.modifier_count);
+        writer.u32(self.unk24);
+    }
+}
+
+/// Section 8. Alters a property, usually by multiplying it with a random
+/// value or one driven by an external input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Modifier {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub field_count: u32,
+    pub property_count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Link,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Link,
+}
+
+impl Record for Modifier {
+    const SIZE: usize = 0x20;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            field_count: reader.u32(offset + 0x8)?,
+            property_count: reader.u32(offset + 0xc)?,
+            fields: links.read(reader, offset + 0x10, Section::Fields)?,
+            properties: links.read(reader, offset + 0x18, Section::ModifierProperties)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.field_count);
+        writer.u32(self.property_count);
+        links.write(writer, self.fields, Section::Fields);
+        links.write(writer, self.properties, Section::ModifierProperties);
+    }
+}
+
+/// Section 9. Same as a property but can't be modified itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModifierProperty {
+    pub kind: u16,
+    pub unk02: u8,
+    pub unk03: u8,
+    pub unk04: u32,
+    pub field_count: u32,
+    pub unk0c: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Link,
+}
+
+impl Record for ModifierProperty {
+    const SIZE: usize = 0x18;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            kind: reader.u16(offset)?,
+            unk02: reader.u8(offset + 0x2)?,
+            unk03: reader.u8(offset + 0x3)?,
+            unk04: reader.u32(offset + 0x4)?,
+            field_count: reader.u32(offset + 0x8)?,
+            unk0c: reader.u32(offset + 0xc)?,
+            fields: links.read(reader, offset + 0x10, Section::Fields)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        writer.u16(self.kind);
+        writer.u8(self.unk02);
+        writer.u8(self.unk03);
+        writer.u32(self.unk04);
+        writer.u32(self.field_count);
+        writer.u32(self.unk0c);
+        links.write(writer, self.fields, Section::Fields);
+    }
+}
+
+/// Section 10. Extra run of fields for an action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldList {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Link,
+    pub field_count: u32,
+    pub unk0c: u32,
+}
+
+impl Record for FieldList {
+    const SIZE: usize = 0x10;
+
+    fn read(reader: &Reader, offset: usize, links: &Links) -> Result<Self, ReadError> {
+        Ok(Self {
+            fields: links.read(reader, offset, Section::Fields)?,
+            field_count: reader.u32(offset + 0x8)?,
+            unk0c: reader.u32(offset + 0xc)?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer, links: &Links) {
+        links.write(writer, self.fields, Section::Fields);
+        writer.u32(self.field_count);
+        writer.u32(self.unk0c);
+    }
+}
+
+/// Section 11. A single 32 bit value, the file doesn't say whether it's an
+/// integer or a float so the bits are kept as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Field(pub u32);
+
+impl Field {
+    pub fn from_int(value: i32) -> Self {
+        Self(value as u32)
+    }
+
+    pub fn from_float(value: f32) -> Self {
+        Self(value.to_bits())
+    }
+
+    pub fn as_int(&self) -> i32 {
+        self.0 as i32
+    }
+
+    pub fn as_float(&self) -> f32 {
+        f32::from_bits(self.0)
+    }
+
+    /// Best guess at whether the field holds a float. Small integers and
+    /// negative ones have either none or all of the exponent bits set, floats
+    /// the effects actually use don't.
+    pub fn is_float(&self) -> bool {
+        !matches!((self.0 >> 23) & 0xff, 0 | 0xff)
+    }
+}
+
+/// Floats are always written with a decimal point or an exponent so the two
+/// kinds survive being turned into text and back.
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.is_float() {
+            true => write!(f, "{:?}", self.as_float()),
+            false => write!(f, "{}", self.as_int()),
+        }
+    }
+}
+
+impl std::str::FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.contains(['.', 'e', 'E']) {
+            s.parse().map(Self::from_float).map_err(|_| format!("{s} is not a float"))
+        } else {
+            s.parse().map(Self::from_int).map_err(|_| format!("{s} is not an integer"))
+        }
+    }
+}
+
+impl Serialize for Field {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Record for Field {
+    const SIZE: usize = 0x4;
+
+    fn read(reader: &Reader, offset: usize, _: &Links) -> Result<Self, ReadError> {
+        Ok(Self(reader.u32(offset)?))
+    }
+
+    fn write(&self, writer: &mut Writer, _: &Links) {
+        writer.u32(self.0);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::ReadError;
+
+/// Bounds checked little endian reads at absolute offsets into an FXR.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], ReadError> {
+        offset.checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or(ReadError::Truncated(offset, len))
+    }
+
+    pub fn u8(&self, offset: usize) -> Result<u8, ReadError> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    pub fn u16(&self, offset: usize) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&self, offset: usize) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into().unwrap()))
+    }
+
+    pub fn u32_be(&self, offset: usize) -> Result<u32, ReadError> {
+        Ok(u32::from_be_bytes(self.bytes(offset, 4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&self, offset: usize) -> Result<u64, ReadError> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into().unwrap()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::Fxr;
+
+/// Resources an FXR pulls in from outside of itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReferenceKind {
+    Texture,
+    Model,
+    Sound,
+    /// Another FXR spawned by this one.
+    Fxr,
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Texture => "texture",
+            Self::Model => "model",
+            Self::Sound => "sound",
+            Self::Fxr => "FXR",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Reference {
+    pub kind: ReferenceKind,
+    pub id: i32,
+    /// Index of the action referencing it.
+    pub action: usize,
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (action[{}])", self.kind, self.id, self.action)
+    }
+}
+
+/// Fields holding IDs of external resources, by action type. Only covers the
+/// action types we know the fields of.
+const REFERENCE_FIELDS: &[(u16, &[(usize, ReferenceKind)])] = &[
+    // Emission sound
+    (75, &[(0, ReferenceKind::Sound)]),
+    // SFX reference
+    (129, &[(0, ReferenceKind::Fxr)]),
+    // Point sprite
+    (600, &[(0, ReferenceKind::Texture)]),
+    // Billboard
+    (603, &[(1, ReferenceKind::Texture), (2, ReferenceKind::Texture)]),
+    // Multi texture billboard
+    (604, &[
+        (1, ReferenceKind::Texture),
+        (2, ReferenceKind::Texture),
+        (3, ReferenceKind::Texture),
+    ]),
+    // Model
+    (605, &[(0, ReferenceKind::Model)]),
+    // Tracer
+    (606, &[(1, ReferenceKind::Texture), (2, ReferenceKind::Texture)]),
+    // Distortion
+    (607, &[
+        (3, ReferenceKind::Texture),
+        (4, ReferenceKind::Texture),
+        (5, ReferenceKind::Texture),
+    ]),
+    // Radial blur
+    (608, &[(3, ReferenceKind::Texture)]),
+];
+
+/// Lists the textures, models, sounds and FXRs the FXR refers to. Fields set
+/// to 0 or -1 don't refer to anything.
+pub fn references(fxr: &Fxr) -> Vec<Reference> {
+    reference_fields(fxr)
+        .map(|(field, kind, action)| Reference {
+            kind,
+            id: fxr.fields[field].as_int(),
+            action,
+        })
+        .filter(|r| r.id > 0)
+        .collect()
+}
+
+/// Indices into the field section of every field that can hold a reference,
+/// along with the kind of reference and the index of the action it's in.
+pub(crate) fn reference_fields(fxr: &Fxr) -> impl Iterator<Item = (usize, ReferenceKind, usize)> + '_ {
+    fxr.actions.iter().enumerate().flat_map(|(index, action)| {
+        let fields = REFERENCE_FIELDS.iter()
+            .find(|(kind, _)| *kind == action.kind)
+            .map_or(&[][..], |(_, fields)| fields);
+
+        let start = action.fields.map(|s| s as usize);
+        let count = action.fields1_count as usize;
+
+        fields.iter()
+            .filter(move |(field, _)| *field < count)
+            .filter_map(move |(field, kind)| Some((start? + field, *kind, index)))
+            .filter(|(field, _, _)| *field < fxr.fields.len())
+    })
+}
+
+/// IDs of the other FXRs this FXR spawns.
+pub fn child_fxrs(fxr: &Fxr) -> Vec<u32> {
+    let mut children = references(fxr).into_iter()
+        .filter(|r| r.kind == ReferenceKind::Fxr)
+        .map(|r| r.id as u32)
+        .collect::<Vec<_>>();
+
+    children.sort();
+    children.dedup();
+    children
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{Fxr, ReadError};
+
+/// The ways an FXR can be stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Json,
+    Xml,
+}
+
+impl Format {
+    /// Picks the format by extension, so both `300.json` and `300.fxr.json`
+    /// are JSON. Anything that isn't JSON or XML is taken to be binary.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("json") => Self::Json,
+            Some("xml") => Self::Xml,
+            _ => Self::Binary,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("Could not read FXR. {0}")]
+    Read(#[from] ReadError),
+    #[error("Invalid FXR JSON. {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid FXR XML. {0}")]
+    Xml(#[from] quick_xml::DeError),
+    #[error("FXR text is not valid UTF-8.")]
+    Encoding,
+}
+
+/// Name of the root element in XML FXRs.
+const XML_ROOT: &str = "fxr";
+
+impl Fxr {
+    pub fn decode(bytes: &[u8], format: Format) -> Result<Self, ConvertError> {
+        match format {
+            Format::Binary => Ok(Self::read(bytes)?),
+            Format::Json => Ok(serde_json::from_slice(bytes)?),
+            Format::Xml => {
+                let text = std::str::from_utf8(bytes).map_err(|_| ConvertError::Encoding)?;
+                Ok(quick_xml::de::from_str(text)?)
+            },
+        }
+    }
+
+    pub fn encode(&self, format: Format) -> Result<Vec<u8>, ConvertError> {
+        match format {
+            Format::Binary => Ok(self.to_bytes()),
+            Format::Json => Ok(serde_json::to_vec_pretty(self)?),
+            Format::Xml => {
+                let mut text = String::new();
+                let mut serializer = quick_xml::se::Serializer::with_root(&mut text, Some(XML_ROOT))?;
+                serializer.indent(' ', 2);
+                self.serialize(serializer)?;
+
+                Ok(text.into_bytes())
+            },
+        }
+    }
+}
+
+/// Reads an FXR in whatever format the file extension says it's in.
+pub fn read_file(path: &Path) -> Result<Fxr, ReadFileError> {
+    let bytes = std::fs::read(path)?;
+
+    Ok(Fxr::decode(&bytes, Format::from_path(path))?)
+}
+
+/// Reads an FXR and hands back its binary form, compiling it first if it's
+/// JSON or XML. Binary files are passed through untouched.
+pub fn read_binary(path: &Path) -> Result<Vec<u8>, ReadFileError> {
+    let bytes = std::fs::read(path)?;
+
+    match Format::from_path(path) {
+        Format::Binary => Ok(bytes),
+        format => Ok(Fxr::decode(&bytes, format)?.to_bytes()),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReadFileError {
+    #[error("Failed to read FXR file. {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Convert(#[from] ConvertError),
+}
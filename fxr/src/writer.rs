@@ -0,0 +1,41 @@
+/// Little endian writes appended to a growing FXR.
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn position(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Pads with zeroes until the position is a multiple of `alignment`.
+    pub fn align(&mut self, alignment: usize) {
+        let padded = self.position().next_multiple_of(alignment);
+        self.bytes.resize(padded, 0);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
@@ -0,0 +1,212 @@
+//! Reading and writing BND4 archives and their DCX containers, over archives
+//! put together here the same way the games lay theirs out.
+
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use fxr::{Archive, ArchiveError, Bnd4, Fxr};
+
+/// Format with IDs, UTF-16 names, long offsets and an uncompressed size per
+/// entry, like the games' FXR archives.
+const FORMAT: u8 = 0x02 | 0x04 | 0x10 | 0x20;
+
+fn fxr(id: u32) -> Vec<u8> {
+    let mut fxr = Fxr::read(include_bytes!("fixtures/aligned.fxr")).unwrap();
+    fxr.id = id;
+    fxr.to_bytes()
+}
+
+/// A BND4 holding the files, with their data 0x10 aligned after the names.
+/// The format byte is stored bit reversed like the games do.
+fn bnd4(files: &[(i32, &str, Vec<u8>)]) -> Vec<u8> {
+    const ENTRY_SIZE: usize = 0x28;
+
+    let entries = 0x40;
+    let names = entries + files.len() * ENTRY_SIZE;
+    let encoded_names = files.iter()
+        .map(|(_, name, _)| name.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let headers_end = names + encoded_names.iter().map(Vec::len).sum::<usize>();
+
+    let mut bytes = vec![0; headers_end];
+    bytes[..4].copy_from_slice(b"BND4");
+    bytes[0xc..0x10].copy_from_slice(&(files.len() as u32).to_le_bytes());
+    bytes[0x20..0x28].copy_from_slice(&(ENTRY_SIZE as u64).to_le_bytes());
+    bytes[0x28..0x30].copy_from_slice(&(headers_end as u64).to_le_bytes());
+    bytes[0x30] = 1;
+    bytes[0x31] = FORMAT.reverse_bits();
+
+    let mut name_offset = names;
+    for (i, ((id, _, data), name)) in files.iter().zip(&encoded_names).enumerate() {
+        bytes.resize(bytes.len().next_multiple_of(0x10), 0);
+        let data_offset = bytes.len();
+        bytes.extend(data);
+
+        let entry = entries + i * ENTRY_SIZE;
+        bytes[entry] = 0x40;
+        bytes[entry + 0x8..entry + 0x10].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes[entry + 0x10..entry + 0x18].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes[entry + 0x18..entry + 0x20].copy_from_slice(&(data_offset as u64).to_le_bytes());
+        bytes[entry + 0x20..entry + 0x24].copy_from_slice(&id.to_le_bytes());
+        bytes[entry + 0x24..entry + 0x28].copy_from_slice(&(name_offset as u32).to_le_bytes());
+
+        bytes[name_offset..name_offset + name.len()].copy_from_slice(name);
+        name_offset += name.len();
+    }
+
+    bytes
+}
+
+/// Wraps data in a DCX container using the supplied compression.
+fn dcx(compression: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut bytes = vec![0; 0x4c];
+    bytes[..4].copy_from_slice(b"DCX\0");
+    bytes[0x4..0x8].copy_from_slice(&0x11000u32.to_be_bytes());
+    bytes[0x8..0xc].copy_from_slice(&0x18u32.to_be_bytes());
+    bytes[0xc..0x10].copy_from_slice(&0x24u32.to_be_bytes());
+    bytes[0x18..0x1c].copy_from_slice(b"DCS\0");
+    bytes[0x1c..0x20].copy_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes[0x20..0x24].copy_from_slice(&(compressed.len() as u32).to_be_bytes());
+
+    // DCP block naming the compression, then the DCA block
+    bytes[0x24..0x28].copy_from_slice(b"DCP\0");
+    bytes[0x28..0x2c].copy_from_slice(compression);
+    bytes[0x2c..0x30].copy_from_slice(&0x20u32.to_be_bytes());
+    bytes[0x30] = 9;
+    bytes[0x44..0x48].copy_from_slice(b"DCA\0");
+    bytes[0x48..0x4c].copy_from_slice(&0x8u32.to_be_bytes());
+
+    bytes.extend(compressed);
+    bytes
+}
+
+fn archive() -> Vec<u8> {
+    bnd4(&[
+        (0, "f000000300.fxr", fxr(300)),
+        (1, "f000000301.fxr", fxr(301)),
+        (2, "readme.txt", b"not an FXR".to_vec()),
+    ])
+}
+
+#[test]
+fn reads_the_files_of_a_bnd4() {
+    let bnd = Bnd4::read(&archive()).unwrap();
+
+    assert_eq!(bnd.files.len(), 3);
+    assert_eq!(bnd.files[1].id, Some(1));
+    assert_eq!(bnd.files[1].name.as_deref(), Some("f000000301.fxr"));
+    assert_eq!(bnd.files[1].data, fxr(301));
+}
+
+#[test]
+fn untouched_bnd4s_are_written_back_byte_identical() {
+    let bytes = archive();
+
+    assert!(Bnd4::read(&bytes).unwrap().to_bytes() == bytes);
+}
+
+#[test]
+fn replaced_files_move_the_ones_after_them() {
+    let mut archive = Archive::read(&archive()).unwrap();
+    let mut bigger = Fxr::read(&fxr(300)).unwrap();
+    bigger.section12.extend([0; 8]);
+    archive.replace_fxr(bigger.to_bytes()).unwrap();
+
+    let reread = Archive::read(&archive.to_bytes().unwrap()).unwrap();
+    assert_eq!(reread.fxr(300).unwrap(), bigger.to_bytes());
+    assert_eq!(reread.fxr(301).unwrap(), fxr(301));
+    assert_eq!(reread.bnd.files[2].data, b"not an FXR");
+    assert_eq!(reread.fxrs().map(|(id, _)| id).collect::<Vec<_>>(), [300, 301]);
+}
+
+#[test]
+fn replacing_needs_an_fxr_with_that_id() {
+    let mut archive = Archive::read(&archive()).unwrap();
+
+    assert!(matches!(archive.replace_fxr(fxr(302)), Err(ArchiveError::MissingFxr(302))));
+    assert!(matches!(archive.replace_fxr(b"FXR".to_vec()), Err(ArchiveError::InvalidFxr(_))));
+}
+
+#[test]
+fn big_endian_bnd4s_are_refused() {
+    let mut bytes = archive();
+    bytes[0x9] = 1;
+
+    assert!(matches!(Bnd4::read(&bytes), Err(ArchiveError::BigEndian)));
+}
+
+#[test]
+fn format_bytes_flagged_big_endian_are_read_as_is() {
+    let mut bytes = archive();
+    bytes[0xa] = 1;
+    bytes[0x31] = FORMAT;
+
+    let bnd = Bnd4::read(&bytes).unwrap();
+    assert_eq!(bnd.files[0].name.as_deref(), Some("f000000300.fxr"));
+    assert!(bnd.to_bytes() == bytes);
+}
+
+#[test]
+fn compressed_entries_are_refused() {
+    let mut bytes = archive();
+    let entry = 0x40 + 0x28;
+    bytes[entry + 0x10..entry + 0x18].copy_from_slice(&0x1000u64.to_le_bytes());
+
+    assert!(matches!(Bnd4::read(&bytes), Err(ArchiveError::CompressedEntry(1))));
+}
+
+#[test]
+fn entries_that_dont_fit_are_refused() {
+    let mut bytes = archive();
+    bytes[0xc..0x10].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(Bnd4::read(&bytes), Err(ArchiveError::Entries(_, 0x28))));
+
+    // Enough entries to overflow the size of the table
+    bytes[0x20..0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(Bnd4::read(&bytes), Err(ArchiveError::Entries(_, _))));
+
+    bytes[0x20..0x28].copy_from_slice(&0u64.to_le_bytes());
+    assert!(matches!(Bnd4::read(&bytes), Err(ArchiveError::Entries(_, 0))));
+}
+
+#[test]
+fn dcx_sizes_are_not_trusted() {
+    let mut bytes = dcx(b"DFLT", &archive());
+    bytes[0x1c..0x20].copy_from_slice(&u32::MAX.to_be_bytes());
+
+    assert_eq!(Archive::read(&bytes).unwrap().fxr(300).unwrap(), fxr(300));
+}
+
+#[test]
+fn dflt_containers_round_trip() {
+    let bytes = dcx(b"DFLT", &archive());
+    let mut archive = Archive::read(&bytes).unwrap();
+    assert_eq!(archive.fxr(300).unwrap(), fxr(300));
+
+    let mut edited = Fxr::read(&fxr(301)).unwrap();
+    edited.unk08 = 0xff;
+    archive.replace_fxr(edited.to_bytes()).unwrap();
+
+    // Still a DFLT container, with the header carried over
+    let written = archive.to_bytes().unwrap();
+    assert_eq!(written[..0x1c], bytes[..0x1c]);
+    assert_eq!(written[0x24..0x4c], bytes[0x24..0x4c]);
+    assert_eq!(Archive::read(&written).unwrap().fxr(301).unwrap(), edited.to_bytes());
+}
+
+#[test]
+fn other_dcx_compressions_are_refused() {
+    let result = Archive::read(&dcx(b"KRAK", &archive()));
+
+    assert!(matches!(result, Err(ArchiveError::UnsupportedCompression(name)) if name == "KRAK"));
+}
+
+#[test]
+fn other_files_are_refused() {
+    assert!(matches!(Archive::read(&fxr(300)), Err(ArchiveError::Magic)));
+}
@@ -0,0 +1,109 @@
+//! Round trips over the FXRs in `fixtures/`. They're small synthetic files
+//! that use every section:
+//!  - `aligned.fxr` is a version 5 FXR laid out the way the games' files
+//!    are, sections in order and pointer aligned.
+//!  - `unaligned.fxr` has the same records behind a word of junk after the
+//!    header, leaving the pointer aligned sections 4 bytes off, with the
+//!    fields moved up front and junk after the last section.
+//!  - `v4.fxr` is a version 4 FXR with a state machine and a root node.
+
+use fxr::{Field, Format, Fxr, Header, Section};
+
+const ALIGNED: &[u8] = include_bytes!("fixtures/aligned.fxr");
+const UNALIGNED: &[u8] = include_bytes!("fixtures/unaligned.fxr");
+const V4: &[u8] = include_bytes!("fixtures/v4.fxr");
+
+const FIXTURES: [(&str, &[u8]); 3] = [("aligned", ALIGNED), ("unaligned", UNALIGNED), ("v4", V4)];
+
+#[test]
+fn fixtures_are_written_back_byte_identical() {
+    for (name, bytes) in FIXTURES {
+        let fxr = Fxr::read(bytes).unwrap();
+        assert!(fxr.to_bytes() == bytes, "{name} changed on the way back");
+    }
+}
+
+#[test]
+fn fixtures_survive_being_turned_into_text() {
+    for (name, bytes) in FIXTURES {
+        let fxr = Fxr::read(bytes).unwrap();
+
+        for format in [Format::Json, Format::Xml] {
+            let text = fxr.encode(format).unwrap();
+            let decoded = Fxr::decode(&text, format).unwrap();
+
+            assert_eq!(decoded, fxr, "{name} changed going through {format:?}");
+            assert!(decoded.to_bytes() == bytes, "{name} compiled from {format:?} isn't identical");
+        }
+    }
+}
+
+#[test]
+fn reads_every_section() {
+    let fxr = Fxr::read(ALIGNED).unwrap();
+
+    assert_eq!(fxr.id, 1000);
+    assert_eq!((fxr.unk04, fxr.unk08, fxr.unk68, fxr.unk6c, fxr.unk88, fxr.unk8c), (0x1234, 0xa, 0x68, 0x6c, 0x88, 0x8c));
+    for section in Section::ALL.into_iter().filter(|s| *s != Section::Section14) {
+        assert_ne!(fxr.section_len(section), 0, "{section:?} is empty");
+    }
+
+    let root = fxr.root().unwrap();
+    assert_eq!(root.nodes, Some(1));
+    assert_eq!(fxr.nodes[1].effects, Some(0));
+    assert_eq!(fxr.effects[0].actions, Some(0));
+
+    let action = &fxr.actions[0];
+    assert_eq!(action.kind, 600);
+    assert_eq!(fxr::span(&fxr.fields, action.fields, action.field_count()), [Field::from_float(1.5), Field(7)]);
+    assert_eq!(fxr.properties[0].modifiers, Some(0));
+    assert_eq!(fxr.modifiers[0].properties, Some(0));
+    assert_eq!(fxr.field_lists[0].fields, Some(7));
+    assert_eq!(fxr.section12, [12, 0x120]);
+}
+
+#[test]
+fn unaligned_layouts_read_the_same_records() {
+    let aligned = Fxr::read(ALIGNED).unwrap();
+    let unaligned = Fxr::read(UNALIGNED).unwrap();
+
+    assert_eq!(unaligned.nodes, aligned.nodes);
+    assert_eq!(unaligned.actions, aligned.actions);
+    assert_eq!(unaligned.fields, aligned.fields);
+    assert_eq!(unaligned.section13, aligned.section13);
+
+    let header = Header::read(UNALIGNED).unwrap();
+    assert_eq!(header.section(4).unwrap().offset % 8, 4);
+}
+
+#[test]
+fn edited_fxrs_stay_aligned() {
+    let mut fxr = Fxr::read(ALIGNED).unwrap();
+    fxr.transitions.push(fxr.transitions[0].clone());
+    fxr.fields.push(Field(0));
+    fxr.section12.push(0);
+
+    let bytes = fxr.to_bytes();
+    let header = Header::read(&bytes).unwrap();
+    for (entry, section) in header.sections.iter().zip(Section::ALL) {
+        let alignment = if section.record_size() == 4 { 4 } else { 8 };
+        assert_eq!(entry.offset as usize % alignment, 0, "{section:?} is at {:#x}", entry.offset);
+    }
+
+    // Links still resolve to the same records
+    let reread = Fxr::read(&bytes).unwrap();
+    assert_eq!(reread.transitions.len(), 2);
+    assert_eq!(reread.nodes, fxr.nodes);
+    assert_eq!(reread.actions, fxr.actions);
+    assert_eq!(reread.fields.len(), 9);
+    assert_eq!(reread.section12, [12, 0x120, 0]);
+}
+
+#[test]
+fn version_4_has_no_later_sections() {
+    let fxr = Fxr::read(V4).unwrap();
+
+    assert_eq!(fxr.version, fxr::Version::V4);
+    assert_eq!((fxr.unk88, fxr.unk8c), (0, 0));
+    assert_eq!(Header::read(&fxr.to_bytes()).unwrap().sections.len(), 11);
+}
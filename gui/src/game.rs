@@ -1,15 +1,9 @@
-use std::fs;
 use std::path::Path;
-use dll_syringe::error::EjectError;
-use dll_syringe::error::LoadProcedureError;
-use dll_syringe::rpc::PayloadRpcError;
+use host::{Agent, Session, SessionError};
 use thiserror::Error;
-use dll_syringe::{Syringe, process::OwnedProcess, error::InjectError};
-use protocol::PatchFxrError;
+use protocol::{FxrPayload, GameCapabilities, PatchOptions, PatchOutcome};
 use sysinfo::{Pid, System};
 
-const AGENT_DLL_NAME: &str = "fxr_reloader_agent.dll";
-
 const SUPPORTED_GAMES: [&str; 4] = [
     "eldenring.exe",
     "armoredcore6.exe",
@@ -38,55 +32,63 @@ pub(crate) fn get_running_games() -> Vec<GameProcess> {
 
 #[derive(Error, Debug)]
 pub(crate) enum PatchError {
-    #[error("Failed to find specified process.")]
-    FindingProcess,
-    #[error("Failed to locate agent module after injection. {0}")]
-    ModuleAcquisition(#[from] InjectError),
-    #[error("Failed to read input FXR file. {0}")]
-    InputFileRead(#[from] std::io::Error),
-    #[error("Failed to patch FXR definition. {0}")]
-    Patch(#[from] PatchFxrError),
-    #[error("Encountered error with the syringe payload. {0}")]
-    Payload(#[from] PayloadRpcError),
-    #[error("Encountered error with the syringe load procedure. {0}")]
-    LoadProcedure(#[from] LoadProcedureError),
-    #[error("Failed to locate the RPC function after injecting agent.")]
-    MissingPatchFunction,
-    #[error("Failed to eject agent module after usage. {0}")]
-    Eject(#[from] EjectError),
+    #[error("{0}")]
+    InputFileRead(#[from] fxr::ReadFileError),
+    #[error("{0}")]
+    Archive(#[from] fxr::ArchiveError),
+    #[error("{0}")]
+    Session(#[from] SessionError),
 }
 
-/// This function injects the agent DLL into the supplied process (if it's not in the process yet)
-/// and calls the exposed `PatchFxr` function on it. We supply the selected FXR file's bytes to
-/// `PatchFxr` when calling it. The Vec<u8> passed into `PatchFxr` is serialized with bincode
-/// to avoid the unsafety around directly passing around `Vec<_>` across FFI barriers.
-/// Once the `PatchFxr` method is done this function will eject the agent again.
+/// Attaches to the supplied process, injecting the agent if it's not in the process yet, and
+/// hands the selected FXR files' bytes over for patching. FXRs made for a different game than
+/// the one running are rejected before anything is sent over.
+/// The agent is left in the process since it hooks the game to apply the swaps, subsequent
+/// calls reuse it.
 pub(crate) fn call_fxr_patch<P: AsRef<Path>>(
     process: Pid,
     files: &[P],
-) -> Result<(), PatchError> {
-    let target_process = OwnedProcess::from_pid(process.as_u32())
-        .map_err(|_| PatchError::FindingProcess)?;
+    options: &PatchOptions,
+) -> Result<PatchReport, PatchError> {
+    // Read the specified FXR files, compiling the JSON and XML ones
+    patch(process, host::read_payloads(files)?, options)
+}
 
-    // Obtain an instance of the agent DLL in the remote process
-    let syringe = Syringe::for_process(target_process);
-    let agent_module = syringe.find_or_inject(AGENT_DLL_NAME)?;
+/// Same as `call_fxr_patch` but takes the FXRs with the supplied IDs out of a game archive.
+pub(crate) fn call_archive_patch(
+    process: Pid,
+    archive: &Path,
+    fxr_ids: &[u32],
+    options: &PatchOptions,
+) -> Result<PatchReport, PatchError> {
+    patch(process, host::archive_payloads(archive, fxr_ids)?, options)
+}
 
-    // Read the specified FXR file
-    let file_contents = files.iter()
-        .map(fs::read)
-        .collect::<Result<Vec<_>, _>>()?;
+fn patch(process: Pid, fxrs: Vec<FxrPayload>, options: &PatchOptions) -> Result<PatchReport, PatchError> {
+    let session = Session::attach(process.as_u32())?;
 
-    // Prepare a call to the agent DLL's patch function
-    let remote_fn = unsafe {
-        syringe.get_payload_procedure::<fn(Vec<Vec<u8>>) -> Result<(), PatchFxrError>>(agent_module, "PatchFxr")
-    }?.ok_or(PatchError::MissingPatchFunction)?;
+    let missing_children = session.missing_children(&fxrs)?;
+    let outcomes = session.patch(fxrs, options)?;
 
-    // Call the thing with the FXR contents
-    remote_fn.call(&file_contents)??;
+    Ok(PatchReport { missing_children, outcomes })
+}
+
+pub(crate) struct PatchReport {
+    /// Parent and child ID of spawned FXRs the game doesn't have loaded.
+    pub missing_children: Vec<(u32, u32)>,
+    pub outcomes: Vec<PatchOutcome>,
+}
+
+/// Asks the agent what it can do in the game, so unsupported operations can
+/// be disabled up front.
+pub(crate) fn call_capabilities(process: Pid) -> Result<GameCapabilities, PatchError> {
+    Ok(Session::attach(process.as_u32())?.capabilities().clone())
+}
 
-    // Remove agent DLL from remote process memory again
-    syringe.eject(agent_module)?;
+/// Tells the agent to stop overriding FXRs. Pending FXRs are dropped and
+/// persistent FXRs are no longer swapped in when the game reloads them.
+pub(crate) fn call_clear_overrides(process: Pid) -> Result<(), PatchError> {
+    Session::attach(process.as_u32())?.clear_overrides(None)?;
 
     Ok(())
 }
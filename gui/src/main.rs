@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use eframe::egui::{self, Style, Vec2, Visuals};
+use protocol::{Capability, GameCapabilities, PatchOptions, PatchOutcome};
 
 mod game;
 
@@ -27,7 +28,12 @@ fn main() {
 #[derive(Default)]
 struct FxrReloaderApp {
     selected_process: Option<game::GameProcess>,
+    /// What the agent can do in the selected process, if we got to ask it.
+    capabilities: Option<GameCapabilities>,
     selected_files: Vec<PathBuf>,
+    persistent: bool,
+    dry_run: bool,
+    archive_ids: String,
     log_entries: Vec<String>,
 }
 
@@ -38,23 +44,106 @@ impl FxrReloaderApp {
             ..Style::default()
         });
 
-        Self {
+        let mut app = Self {
             selected_process: game::get_running_games().first().cloned(),
             ..Self::default()
-        }
+        };
+
+        app.refresh_capabilities();
+        app
+    }
+
+    fn refresh_capabilities(&mut self) {
+        let Some(process) = self.selected_process.as_ref() else {
+            self.capabilities = None;
+            return;
+        };
+
+        self.capabilities = match game::call_capabilities(process.pid) {
+            Ok(capabilities) => Some(capabilities),
+            Err(e) => {
+                self.log_entries.push(format!("Failed to query the agent: {e}"));
+                None
+            },
+        };
+    }
+
+    /// Whether an operation can be attempted on the selected process. If the
+    /// agent couldn't be asked we let the operation report what's wrong.
+    fn supports(&self, capability: Capability) -> bool {
+        self.selected_process.is_some()
+            && self.capabilities.as_ref().is_none_or(|c| c.supports(capability))
     }
 
     fn reload_selected_fxrs(&mut self) {
         let result = game::call_fxr_patch(
             self.selected_process.as_ref().unwrap().pid,
-            &self.selected_files
+            &self.selected_files,
+            &self.patch_options(),
+        );
+
+        self.log_patch_result(result);
+    }
+
+    fn reload_from_archive(&mut self, archive: PathBuf) {
+        let ids = self.archive_ids.split([',', ' '])
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>();
+
+        let ids = match ids {
+            Ok(ids) if !ids.is_empty() => ids,
+            _ => {
+                self.log_entries.push(String::from("Enter the IDs of the FXRs to take from the archive"));
+                return;
+            },
+        };
+
+        let result = game::call_archive_patch(
+            self.selected_process.as_ref().unwrap().pid,
+            &archive,
+            &ids,
+            &self.patch_options(),
         );
 
+        self.log_patch_result(result);
+    }
+
+    fn patch_options(&self) -> PatchOptions {
+        PatchOptions {
+            persistent: self.persistent,
+            dry_run: self.dry_run,
+        }
+    }
+
+    fn log_patch_result(&mut self, result: Result<game::PatchReport, game::PatchError>) {
         match result {
-            Ok(_) => self.log_entries.push(String::from("Reloaded FXR")),
+            Ok(report) => {
+                report.missing_children.iter().for_each(|(parent, child)| self.log_entries.push(
+                    format!("Warning: FXR {parent} spawns FXR {child} which the game doesn't have loaded"),
+                ));
+
+                report.outcomes.iter().for_each(|o| self.log_entries.push(match o {
+                    PatchOutcome::Patched(id) => format!("Reloaded FXR {id}"),
+                    PatchOutcome::Pending(id) => format!("FXR {id} is not loaded, reloading once the game loads it"),
+                    PatchOutcome::WouldPatch(id) => format!("FXR {id} would be reloaded"),
+                    PatchOutcome::WouldQueue(id) => format!("FXR {id} is not loaded, it would be reloaded once the game loads it"),
+                }));
+            },
             Err(e) => self.log_entries.push(format!("Failed to reload FXR: {e}")),
         }
     }
+
+    fn clear_overrides(&mut self) {
+        let result = game::call_clear_overrides(
+            self.selected_process.as_ref().unwrap().pid,
+        );
+
+        match result {
+            Ok(_) => self.log_entries.push(String::from("Cleared overrides")),
+            Err(e) => self.log_entries.push(format!("Failed to clear overrides: {e}")),
+        }
+    }
 }
 
 impl eframe::App for FxrReloaderApp {
@@ -71,20 +160,23 @@ impl eframe::App for FxrReloaderApp {
                 )
                 .show_ui(ui, |ui| {
                     game::get_running_games().iter().for_each(|e| {
-                        ui.selectable_value(
+                        if ui.selectable_value(
                             &mut self.selected_process,
                             Some(e.clone()),
                             format_process_label(e),
-                        );
+                        ).changed() {
+                            self.refresh_capabilities();
+                        }
                     })
                 });
 
             if ui.add_enabled(
-                self.selected_process.is_some(),
+                self.supports(Capability::Patch),
                 egui::Button::new("Patch FXR")
             ).clicked() {
                 if let Some(fxrs) = rfd::FileDialog::new()
                     .add_filter("FXR Files", &["fxr"])
+                    .add_filter("FXR JSON/XML Files", &["json", "xml"])
                     .pick_files() {
 
                     self.selected_files = fxrs.iter()
@@ -96,12 +188,48 @@ impl eframe::App for FxrReloaderApp {
             }
 
             if ui.add_enabled(
-                self.selected_process.is_some() && !self.selected_files.is_empty(),
+                self.supports(Capability::Patch) && !self.selected_files.is_empty(),
                 egui::Button::new("Reload last reloaded FXRs")
             ).clicked() {
                 self.reload_selected_fxrs();
             }
 
+            ui.horizontal(|ui| {
+                ui.label("Archive FXR IDs");
+                ui.text_edit_singleline(&mut self.archive_ids);
+            });
+
+            if ui.add_enabled(
+                self.supports(Capability::Patch),
+                egui::Button::new("Patch FXR from archive")
+            ).clicked() {
+                if let Some(archive) = rfd::FileDialog::new()
+                    .add_filter("FXR Archives", &["dcx", "ffxbnd"])
+                    .pick_file() {
+
+                    self.reload_from_archive(archive);
+                }
+            }
+
+            let persistent_supported = self.supports(Capability::PersistentOverrides);
+            if !persistent_supported {
+                self.persistent = false;
+            }
+
+            ui.add_enabled(
+                persistent_supported,
+                egui::Checkbox::new(&mut self.persistent, "Keep reloading when the game reloads the FXRs"),
+            );
+
+            ui.checkbox(&mut self.dry_run, "Dry run, only check what reloading would do");
+
+            if ui.add_enabled(
+                self.selected_process.is_some(),
+                egui::Button::new("Clear overrides")
+            ).clicked() {
+                self.clear_overrides();
+            }
+
             let mut log_buffer = self.log_entries.join("\n");
             ui.add_enabled(
                 false,
@@ -0,0 +1,120 @@
+use protocol::{
+    Capability, ControlServerOptions, FxrDump, FxrPayload, GameCapabilities, MemoryStats,
+    OverrideEntry, PatchOptions, PatchOutcome, RunningGame,
+};
+
+use crate::SessionError;
+
+/// Everything a host can ask of an agent. `Session` talks to the agent
+/// injected into a game, `MockAgent` simulates one so hosts can be exercised
+/// without a game.
+pub trait Agent {
+    /// What the agent can do in the game it's running in.
+    fn capabilities(&self) -> &GameCapabilities;
+
+    /// The game the agent reported running in.
+    fn game(&self) -> RunningGame {
+        self.capabilities().game
+    }
+
+    /// Sends the FXRs over to the agent for patching. FXRs made for a
+    /// different game are rejected before anything is sent.
+    fn patch(&self, fxrs: Vec<FxrPayload>, options: &PatchOptions) -> Result<Vec<PatchOutcome>, SessionError>;
+
+    /// Adds FXRs under IDs that the game doesn't have loaded.
+    fn insert(&self, fxrs: Vec<FxrPayload>) -> Result<(), SessionError>;
+
+    fn restore(&self, fxr_ids: Vec<u32>) -> Result<(), SessionError>;
+
+    /// IDs of the FXRs the game has loaded.
+    fn resident_fxrs(&self) -> Result<Vec<u32>, SessionError>;
+
+    /// Child FXRs spawned by the supplied FXRs that the game doesn't have
+    /// loaded, as pairs of parent and child ID. The child won't show up
+    /// until the game loads it. Children that are part of the supplied FXRs
+    /// are fine. FXRs we can't fully parse are skipped, this is only a
+    /// warning and the agent only needs their header to patch them.
+    fn missing_children(&self, fxrs: &[FxrPayload]) -> Result<Vec<(u32, u32)>, SessionError> {
+        // Nothing to compare against
+        if !self.capabilities().supports(Capability::ListResident) {
+            return Ok(Vec::new());
+        }
+
+        let parsed = fxrs.iter()
+            .filter_map(|f| fxr::Fxr::read(&f.bytes).ok())
+            .collect::<Vec<_>>();
+
+        let mut available = self.resident_fxrs()?;
+        available.extend(fxrs.iter().filter_map(|f| fxr::Header::read(&f.bytes).ok()).map(|h| h.id));
+
+        Ok(parsed.iter()
+            .flat_map(|f| fxr::child_fxrs(f).into_iter().map(move |child| (f.id, child)))
+            .filter(|(_, child)| !available.contains(child))
+            .collect())
+    }
+
+    /// Copies the definition the game currently has loaded for an FXR.
+    fn dump_fxr(&self, fxr_id: u32) -> Result<FxrDump, SessionError>;
+
+    fn memory_stats(&self) -> Result<MemoryStats, SessionError>;
+
+    fn list_overrides(&self) -> Result<Vec<OverrideEntry>, SessionError>;
+
+    /// Stops overriding the specified FXRs, or all of them if `None`.
+    fn clear_overrides(&self, fxr_ids: Option<Vec<u32>>) -> Result<(), SessionError>;
+
+    /// Has the agent open its control channels, after which other tools can
+    /// talk to it without injecting anything.
+    fn start_control_server(&self, options: ControlServerOptions) -> Result<(), SessionError>;
+}
+
+/// Fails with a descriptive error if the agent can't perform the operation
+/// in its game, rather than letting the agent fail on it.
+pub(crate) fn require(capabilities: &GameCapabilities, capability: Capability) -> Result<(), SessionError> {
+    match capabilities.supports(capability) {
+        true => Ok(()),
+        false => Err(SessionError::Unsupported(
+            capability,
+            capabilities.game,
+            capabilities.version.clone(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fxr, payload, truncated_fxr};
+    use crate::MockAgent;
+
+    #[test]
+    fn missing_children_are_the_ones_nothing_provides() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(2000, fxr(2000, None));
+        let fxrs = [
+            payload(fxr(1000, Some(2000))),
+            payload(fxr(1001, Some(1002))),
+            payload(fxr(1002, Some(3000))),
+        ];
+
+        assert_eq!(agent.missing_children(&fxrs).unwrap(), [(1002, 3000)]);
+    }
+
+    #[test]
+    fn missing_children_skips_fxrs_that_dont_parse() {
+        let agent = MockAgent::new(RunningGame::EldenRing);
+        let fxrs = [
+            payload(truncated_fxr(1000, Some(3000))),
+            payload(fxr(1001, Some(1000))),
+        ];
+
+        // The broken one still provides its ID
+        assert!(agent.missing_children(&fxrs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_children_needs_the_resident_list() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_capabilities(&[Capability::Patch]);
+
+        assert!(agent.missing_children(&[payload(fxr(1000, Some(3000)))]).unwrap().is_empty());
+    }
+}
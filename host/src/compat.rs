@@ -0,0 +1,86 @@
+use fxr::{Family, Header};
+use protocol::RunningGame;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CompatibilityError {
+    #[error("Could not read FXR. {0}")]
+    Read(#[from] fxr::ReadError),
+    #[error("FXR {0} is made for {1} and can't be loaded by {2}.")]
+    WrongGame(u32, Family, RunningGame),
+    #[error("FXR {0} only uses actions Sekiro already had, so it's taken for a Sekiro FXR and can't be loaded by {1}.")]
+    SekiroFxr(u32, RunningGame),
+}
+
+/// The FXR families a game knows how to load. Only Elden Ring can be told
+/// apart by the agent so far, there's no `RunningGame` for Sekiro yet and so
+/// no way to refuse Elden Ring FXRs being sent to it.
+fn supported_families(game: RunningGame) -> &'static [Family] {
+    match game {
+        RunningGame::EldenRing => &[Family::EldenRing],
+        // RunningGame::ArmoredCore6 => &[Family::EldenRing],
+    }
+}
+
+/// Checks that the FXR was made for a game in the same family as the one
+/// we're about to send it to. The game will happily take an FXR it can't make
+/// sense of and crash on it later.
+pub fn check_compatibility(game: RunningGame, fxr: &[u8]) -> Result<(), CompatibilityError> {
+    let header = Header::read(fxr)?;
+    let family = header.family(fxr)?;
+
+    if !supported_families(game).contains(&family) {
+        return Err(match family {
+            Family::Sekiro => CompatibilityError::SekiroFxr(header.id, game),
+            _ => CompatibilityError::WrongGame(header.id, family, game),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use fxr::Version;
+
+    use super::*;
+
+    /// An FXR with a single action of the supplied type.
+    fn fxr(version: Version, action: u16) -> Vec<u8> {
+        let offset = version.header_size();
+        let mut bytes = vec![0; offset + 0x40];
+        bytes[..4].copy_from_slice(b"FXR\0");
+        bytes[0x6..0x8].copy_from_slice(&version.raw().to_le_bytes());
+        bytes[0xc..0x10].copy_from_slice(&300u32.to_le_bytes());
+        bytes[0x38..0x3c].copy_from_slice(&(offset as u32).to_le_bytes());
+        bytes[0x3c..0x40].copy_from_slice(&1u32.to_le_bytes());
+        bytes[offset..offset + 2].copy_from_slice(&action.to_le_bytes());
+
+        bytes
+    }
+
+    #[test]
+    fn elden_ring_loads_elden_ring_fxrs() {
+        assert!(check_compatibility(RunningGame::EldenRing, &fxr(Version::V5, 10014)).is_ok());
+    }
+
+    #[test]
+    fn elden_ring_refuses_sekiro_fxrs() {
+        let result = check_compatibility(RunningGame::EldenRing, &fxr(Version::V5, 600));
+        assert!(matches!(result, Err(CompatibilityError::SekiroFxr(300, RunningGame::EldenRing))));
+    }
+
+    #[test]
+    fn elden_ring_refuses_dark_souls_3_fxrs() {
+        let result = check_compatibility(RunningGame::EldenRing, &fxr(Version::V4, 600));
+        assert!(matches!(
+            result,
+            Err(CompatibilityError::WrongGame(300, Family::DarkSouls3, RunningGame::EldenRing))
+        ));
+    }
+
+    #[test]
+    fn unreadable_fxrs_are_refused() {
+        assert!(matches!(check_compatibility(RunningGame::EldenRing, b"FXR"), Err(CompatibilityError::Read(_))));
+    }
+}
@@ -0,0 +1,45 @@
+#[cfg(windows)]
+use dll_syringe::error::{EjectError, InjectError, LoadProcedureError};
+#[cfg(windows)]
+use dll_syringe::rpc::PayloadRpcError;
+use protocol::{Capability, PatchFxrError, RunningGame};
+use thiserror::Error;
+
+use crate::compat::CompatibilityError;
+
+/// Errors from talking to an agent. The syringe errors only exist on Windows,
+/// the only place the agent can be injected.
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Failed to find specified process.")]
+    FindingProcess,
+    #[cfg(not(windows))]
+    #[error("Agents can only be injected on Windows.")]
+    UnsupportedPlatform,
+    #[cfg(windows)]
+    #[error("Failed to locate agent module after injection. {0}")]
+    ModuleAcquisition(#[from] InjectError),
+    #[cfg(windows)]
+    #[error("Encountered error with the syringe payload. {0}")]
+    Payload(#[from] PayloadRpcError),
+    #[cfg(windows)]
+    #[error("Encountered error with the syringe load procedure. {0}")]
+    LoadProcedure(#[from] LoadProcedureError),
+    #[cfg(windows)]
+    #[error("Failed to unload the outdated agent. {0}")]
+    Eject(#[from] EjectError),
+    #[error("Agent does not expose {0}.")]
+    MissingProcedure(&'static str),
+    #[error("Agent {0} speaks protocol version {1} but {2} is required. Restart the game to use this version.")]
+    ProtocolMismatch(String, u32, u32),
+    #[error("{0} is not supported on {1} {2}.")]
+    Unsupported(Capability, RunningGame, String),
+    #[error("Failed to patch FXR definition. {0}")]
+    Patch(#[from] PatchFxrError),
+    #[error("{0}")]
+    Compatibility(#[from] CompatibilityError),
+    #[error("Agent answered request {0} with a reply for request {1}.")]
+    MismatchedResponse(u64, u64),
+    #[error("Agent sent an unexpected reply to {0}.")]
+    UnexpectedReply(&'static str),
+}
@@ -0,0 +1,19 @@
+mod agent;
+mod compat;
+mod error;
+mod mock;
+mod payload;
+#[cfg(windows)]
+mod session;
+#[cfg(not(windows))]
+#[path = "session_unsupported.rs"]
+mod session;
+#[cfg(test)]
+mod testing;
+
+pub use agent::Agent;
+pub use compat::{check_compatibility, CompatibilityError};
+pub use error::SessionError;
+pub use mock::MockAgent;
+pub use payload::{archive_payloads, read_payloads};
+pub use session::Session;
@@ -0,0 +1,438 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use fxr::Header;
+use protocol::{
+    Capability, ControlServerOptions, FxrDump, FxrPayload, GameCapabilities, MemoryStats,
+    OverrideEntry, PatchFxrError, PatchOptions, PatchOutcome, RunningGame,
+};
+
+use crate::agent::{require, Agent};
+use crate::compat::check_compatibility;
+use crate::SessionError;
+
+/// Stands in for an agent without a game. Keeps a list of loaded FXRs in
+/// memory and treats them the way the agent treats the game's, so hosts can
+/// be run against it.
+pub struct MockAgent {
+    capabilities: GameCapabilities,
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// Definitions the simulated game has loaded.
+    resident: BTreeMap<u32, Vec<u8>>,
+    /// Definitions that were loaded before an FXR got patched. `None` for
+    /// FXRs that were inserted.
+    originals: BTreeMap<u32, Option<Vec<u8>>>,
+    /// FXRs waiting for the game to load them, and whether they're
+    /// persistent.
+    overrides: BTreeMap<u32, (Vec<u8>, bool)>,
+    /// Error the next operation fails with, as if the agent had run into it.
+    next_error: Option<PatchFxrError>,
+}
+
+impl MockAgent {
+    /// A mock that supports everything and has nothing loaded.
+    pub fn new(game: RunningGame) -> Self {
+        Self {
+            capabilities: GameCapabilities {
+                game,
+                version: String::from("mock"),
+                capabilities: Capability::ALL.to_vec(),
+            },
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn with_capabilities(mut self, capabilities: &[Capability]) -> Self {
+        self.capabilities.capabilities = capabilities.to_vec();
+        self
+    }
+
+    pub fn with_resident(self, fxr_id: u32, definition: Vec<u8>) -> Self {
+        self.load(fxr_id, definition);
+        self
+    }
+
+    /// Makes the next operation that reaches the agent fail with `error`.
+    pub fn fail_next(&self, error: PatchFxrError) {
+        self.state.lock().unwrap().next_error = Some(error);
+    }
+
+    /// The definition the simulated game currently has loaded for an FXR.
+    pub fn definition(&self, fxr_id: u32) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().resident.get(&fxr_id).cloned()
+    }
+
+    /// Simulates the game loading an FXR. Like the agent, an FXR waiting
+    /// for this ID is swapped in right away.
+    pub fn load(&self, fxr_id: u32, definition: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+
+        let definition = match state.overrides.get(&fxr_id).cloned() {
+            Some((fxr, persistent)) => {
+                if !persistent {
+                    state.overrides.remove(&fxr_id);
+                }
+
+                state.originals.insert(fxr_id, Some(definition));
+                fxr
+            },
+            None => definition,
+        };
+
+        state.resident.insert(fxr_id, definition);
+    }
+
+    /// Simulates the game unloading an FXR, taking anything we swapped in
+    /// along with it.
+    pub fn unload(&self, fxr_id: u32) {
+        let mut state = self.state.lock().unwrap();
+
+        state.resident.remove(&fxr_id);
+        state.originals.remove(&fxr_id);
+    }
+
+    /// Gets hold of the state for an operation that made it to the agent,
+    /// failing if an error was queued up with `fail_next`.
+    fn reach_agent(&self) -> Result<MutexGuard<'_, MockState>, SessionError> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.next_error.take() {
+            Some(error) => Err(error.into()),
+            None => Ok(state),
+        }
+    }
+}
+
+impl Agent for MockAgent {
+    fn capabilities(&self) -> &GameCapabilities {
+        &self.capabilities
+    }
+
+    fn patch(&self, fxrs: Vec<FxrPayload>, options: &PatchOptions) -> Result<Vec<PatchOutcome>, SessionError> {
+        require(&self.capabilities, Capability::Patch)?;
+        if options.persistent {
+            require(&self.capabilities, Capability::PersistentOverrides)?;
+        }
+
+        fxrs.iter().try_for_each(|f| check_compatibility(self.capabilities.game, &f.bytes))?;
+
+        let mut state = self.reach_agent()?;
+        let fxrs = unpack(fxrs)?;
+
+        // The agent only reports persistent overrides when it hooked the SFX
+        // update, which is also what picks up FXRs once they're loaded
+        let hooked = self.capabilities.capabilities.contains(&Capability::PersistentOverrides);
+
+        if options.dry_run {
+            return Ok(fxrs.iter()
+                .map(|f| {
+                    let fxr_id = read_fxr_id(f)?;
+
+                    match state.resident.contains_key(&fxr_id) {
+                        true => Ok(PatchOutcome::WouldPatch(fxr_id)),
+                        false if hooked => Ok(PatchOutcome::WouldQueue(fxr_id)),
+                        false => Err(PatchFxrError::NotLoaded(fxr_id)),
+                    }
+                })
+                .collect::<Result<_, PatchFxrError>>()?);
+        }
+
+        Ok(fxrs.into_iter()
+            .map(|f| {
+                let fxr_id = read_fxr_id(&f)?;
+
+                if !state.resident.contains_key(&fxr_id) {
+                    if !hooked {
+                        return Err(PatchFxrError::NotLoaded(fxr_id));
+                    }
+
+                    state.overrides.insert(fxr_id, (f, options.persistent));
+                    return Ok(PatchOutcome::Pending(fxr_id));
+                }
+
+                match options.persistent {
+                    true => state.overrides.insert(fxr_id, (f.clone(), true)),
+                    false => state.overrides.remove(&fxr_id),
+                };
+
+                let previous = state.resident.insert(fxr_id, f);
+                state.originals.entry(fxr_id).or_insert(previous);
+
+                Ok(PatchOutcome::Patched(fxr_id))
+            })
+            .collect::<Result<_, PatchFxrError>>()?)
+    }
+
+    fn insert(&self, fxrs: Vec<FxrPayload>) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::Insert)?;
+
+        fxrs.iter().try_for_each(|f| check_compatibility(self.capabilities.game, &f.bytes))?;
+
+        let mut state = self.reach_agent()?;
+        for fxr in unpack(fxrs)? {
+            let fxr_id = read_fxr_id(&fxr)?;
+            if state.resident.contains_key(&fxr_id) {
+                return Err(PatchFxrError::AlreadyLoaded(fxr_id).into());
+            }
+
+            state.resident.insert(fxr_id, fxr);
+            state.originals.insert(fxr_id, None);
+        }
+
+        Ok(())
+    }
+
+    fn restore(&self, fxr_ids: Vec<u32>) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::Restore)?;
+
+        let mut state = self.reach_agent()?;
+        for fxr_id in fxr_ids {
+            let was_pending = state.overrides.remove(&fxr_id).is_some();
+
+            match state.originals.remove(&fxr_id) {
+                Some(Some(original)) => { state.resident.insert(fxr_id, original); },
+                Some(None) => { state.resident.remove(&fxr_id); },
+                None if was_pending => {},
+                None => return Err(PatchFxrError::NotPatched(fxr_id).into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resident_fxrs(&self) -> Result<Vec<u32>, SessionError> {
+        require(&self.capabilities, Capability::ListResident)?;
+
+        Ok(self.reach_agent()?.resident.keys().copied().collect())
+    }
+
+    fn dump_fxr(&self, fxr_id: u32) -> Result<FxrDump, SessionError> {
+        require(&self.capabilities, Capability::Dump)?;
+
+        let state = self.reach_agent()?;
+        let bytes = state.resident.get(&fxr_id)
+            .cloned()
+            .ok_or(PatchFxrError::NotLoaded(fxr_id))?;
+
+        // Nothing got relocated, the definition is as it was sent over
+        Ok(FxrDump { address: 0, bytes })
+    }
+
+    fn memory_stats(&self) -> Result<MemoryStats, SessionError> {
+        require(&self.capabilities, Capability::MemoryStats)?;
+
+        // Like the agent's, inserted FXRs count and only the definitions take
+        // up bytes. Superseded definitions are dropped right away rather than
+        // waiting out a frame, so nothing is ever retired.
+        let state = self.reach_agent()?;
+        Ok(MemoryStats {
+            tracked_fxrs: state.originals.len(),
+            live_bytes: state.originals.keys()
+                .filter_map(|id| state.resident.get(id))
+                .map(Vec::len)
+                .sum(),
+            retired_bytes: 0,
+        })
+    }
+
+    fn list_overrides(&self) -> Result<Vec<OverrideEntry>, SessionError> {
+        let state = self.reach_agent()?;
+
+        Ok(state.overrides.iter()
+            .map(|(fxr_id, (fxr, persistent))| OverrideEntry {
+                fxr_id: *fxr_id,
+                persistent: *persistent,
+                size: fxr.len(),
+            })
+            .collect())
+    }
+
+    fn clear_overrides(&self, fxr_ids: Option<Vec<u32>>) -> Result<(), SessionError> {
+        let mut state = self.reach_agent()?;
+
+        match fxr_ids {
+            Some(fxr_ids) => fxr_ids.iter().for_each(|id| { state.overrides.remove(id); }),
+            None => state.overrides.clear(),
+        }
+
+        Ok(())
+    }
+
+    fn start_control_server(&self, _options: ControlServerOptions) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::ControlServer)?;
+
+        self.reach_agent().map(|_| ())
+    }
+}
+
+/// Refuses payloads that don't match their hash, like the agent does.
+fn unpack(fxrs: Vec<FxrPayload>) -> Result<Vec<Vec<u8>>, PatchFxrError> {
+    fxrs.into_iter()
+        .map(|f| match f.is_intact() {
+            true => Ok(f.bytes),
+            false => Err(PatchFxrError::CorruptPayload(f.describe())),
+        })
+        .collect()
+}
+
+fn read_fxr_id(fxr: &[u8]) -> Result<u32, PatchFxrError> {
+    Header::read(fxr)
+        .map(|header| header.id)
+        .map_err(|_| PatchFxrError::InvalidFxr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fxr, payload, truncated_fxr};
+
+    fn dry_run() -> PatchOptions {
+        PatchOptions { persistent: false, dry_run: true }
+    }
+
+    fn persistent() -> PatchOptions {
+        PatchOptions { persistent: true, dry_run: false }
+    }
+
+    fn agent() -> MockAgent {
+        MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, None))
+    }
+
+    /// An FXR that's told apart from the game's own by the child it spawns.
+    fn edited(id: u32) -> Vec<u8> {
+        fxr(id, Some(9000))
+    }
+
+    #[test]
+    fn patching_swaps_loaded_fxrs() {
+        let agent = agent();
+
+        let outcomes = agent.patch(vec![payload(edited(300))], &PatchOptions::default()).unwrap();
+        assert_eq!(outcomes, [PatchOutcome::Patched(300)]);
+        assert_eq!(agent.definition(300), Some(edited(300)));
+        assert!(agent.list_overrides().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fxrs_that_arent_loaded_are_swapped_in_once_they_are() {
+        let agent = agent();
+
+        let outcomes = agent.patch(vec![payload(edited(301))], &PatchOptions::default()).unwrap();
+        assert_eq!(outcomes, [PatchOutcome::Pending(301)]);
+        assert_eq!(agent.definition(301), None);
+
+        agent.load(301, fxr(301, None));
+        assert_eq!(agent.definition(301), Some(edited(301)));
+        assert!(agent.list_overrides().unwrap().is_empty());
+
+        // One-off overrides are gone once swapped in
+        agent.unload(301);
+        agent.load(301, fxr(301, None));
+        assert_eq!(agent.definition(301), Some(fxr(301, None)));
+    }
+
+    #[test]
+    fn fxrs_that_arent_loaded_need_the_hook() {
+        let agent = agent().with_capabilities(&[Capability::Patch, Capability::ListResident]);
+
+        let result = agent.patch(vec![payload(edited(301))], &PatchOptions::default());
+        assert!(matches!(result, Err(SessionError::Patch(PatchFxrError::NotLoaded(301)))));
+        let result = agent.patch(vec![payload(edited(301))], &dry_run());
+        assert!(matches!(result, Err(SessionError::Patch(PatchFxrError::NotLoaded(301)))));
+
+        // Loaded ones are swapped from the calling thread instead
+        let outcomes = agent.patch(vec![payload(edited(300))], &PatchOptions::default()).unwrap();
+        assert_eq!(outcomes, [PatchOutcome::Patched(300)]);
+
+        let result = agent.patch(vec![payload(edited(300))], &persistent());
+        assert!(matches!(result, Err(SessionError::Unsupported(Capability::PersistentOverrides, ..))));
+    }
+
+    #[test]
+    fn persistent_fxrs_come_back_after_reloads() {
+        let agent = agent();
+        agent.patch(vec![payload(edited(300))], &persistent()).unwrap();
+
+        agent.unload(300);
+        agent.load(300, fxr(300, None));
+        assert_eq!(agent.definition(300), Some(edited(300)));
+
+        let overrides = agent.list_overrides().unwrap();
+        assert_eq!(overrides.len(), 1);
+        assert!(overrides[0].fxr_id == 300 && overrides[0].persistent);
+
+        // Patching it again without the flag lets the game have it back
+        agent.patch(vec![payload(edited(300))], &PatchOptions::default()).unwrap();
+        agent.unload(300);
+        agent.load(300, fxr(300, None));
+        assert_eq!(agent.definition(300), Some(fxr(300, None)));
+    }
+
+    #[test]
+    fn restoring_puts_back_the_games_fxr() {
+        let agent = agent();
+        agent.patch(vec![payload(edited(300))], &persistent()).unwrap();
+        agent.patch(vec![payload(fxr(300, Some(9001)))], &persistent()).unwrap();
+
+        agent.restore(vec![300]).unwrap();
+        assert_eq!(agent.definition(300), Some(fxr(300, None)));
+        assert!(agent.list_overrides().unwrap().is_empty());
+
+        let result = agent.restore(vec![300]);
+        assert!(matches!(result, Err(SessionError::Patch(PatchFxrError::NotPatched(300)))));
+    }
+
+    #[test]
+    fn restoring_drops_pending_and_inserted_fxrs() {
+        let agent = agent();
+        agent.patch(vec![payload(edited(301))], &PatchOptions::default()).unwrap();
+        agent.insert(vec![payload(edited(302))]).unwrap();
+
+        agent.restore(vec![301, 302]).unwrap();
+        agent.load(301, fxr(301, None));
+        assert_eq!(agent.definition(301), Some(fxr(301, None)));
+        assert_eq!(agent.definition(302), None);
+        assert_eq!(agent.resident_fxrs().unwrap(), [300, 301]);
+    }
+
+    #[test]
+    fn dry_runs_leave_the_game_alone() {
+        let agent = agent();
+
+        let fxrs = vec![payload(edited(300)), payload(edited(301))];
+        let outcomes = agent.patch(fxrs, &dry_run()).unwrap();
+        assert_eq!(outcomes, [PatchOutcome::WouldPatch(300), PatchOutcome::WouldQueue(301)]);
+        assert_eq!(agent.definition(300), Some(fxr(300, None)));
+        assert!(agent.list_overrides().unwrap().is_empty());
+        assert_eq!(agent.memory_stats().unwrap().tracked_fxrs, 0);
+    }
+
+    #[test]
+    fn memory_stats_count_patched_and_inserted_definitions() {
+        let agent = agent();
+        agent.patch(vec![payload(edited(300)), payload(edited(301))], &PatchOptions::default()).unwrap();
+        agent.insert(vec![payload(edited(302))]).unwrap();
+
+        // The pending FXR isn't in the game yet
+        let stats = agent.memory_stats().unwrap();
+        assert_eq!(stats.tracked_fxrs, 2);
+        assert_eq!(stats.live_bytes, edited(300).len() + edited(302).len());
+
+        agent.restore(vec![302]).unwrap();
+        assert_eq!(agent.memory_stats().unwrap().tracked_fxrs, 1);
+    }
+
+    #[test]
+    fn dry_runs_accept_what_patching_accepts() {
+        let agent = MockAgent::new(RunningGame::EldenRing).with_resident(300, fxr(300, None));
+
+        // Only the header is checked either way, the records are cut off
+        let fxrs = vec![payload(truncated_fxr(300, None))];
+        assert_eq!(agent.patch(fxrs.clone(), &dry_run()).unwrap(), [PatchOutcome::WouldPatch(300)]);
+        assert_eq!(agent.patch(fxrs, &PatchOptions::default()).unwrap(), [PatchOutcome::Patched(300)]);
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::Path;
+
+use protocol::FxrPayload;
+
+/// Reads FXR files for sending over to the agent, compiling the JSON and XML
+/// ones.
+pub fn read_payloads<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<FxrPayload>, fxr::ReadFileError> {
+    paths.iter()
+        .map(|p| {
+            let bytes = fxr::read_binary(p.as_ref())?;
+            Ok(FxrPayload::new(Some(p.as_ref().display().to_string()), bytes))
+        })
+        .collect()
+}
+
+/// Takes the FXRs with the supplied IDs out of a game archive for sending
+/// over to the agent.
+pub fn archive_payloads(archive: &Path, fxr_ids: &[u32]) -> Result<Vec<FxrPayload>, fxr::ArchiveError> {
+    let fxrs = fxr::read_archive_fxrs(archive, fxr_ids)?;
+
+    Ok(fxr_ids.iter()
+        .zip(fxrs)
+        .map(|(id, bytes)| FxrPayload::new(Some(format!("{} (FXR {id})", archive.display())), bytes))
+        .collect())
+}
@@ -0,0 +1,221 @@
+use dll_syringe::process::{BorrowedProcessModule, ModuleHandle};
+use dll_syringe::{Syringe, process::OwnedProcess};
+use protocol::{
+    AgentInfo, Capability, ControlServerOptions, FxrDump, FxrPayload, GameCapabilities,
+    MemoryStats, Operation, OverrideEntry, PatchOptions, PatchOutcome, Reply, Request, Response,
+    PROTOCOL_VERSION,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::agent::{require, Agent};
+use crate::compat::check_compatibility;
+use crate::SessionError;
+
+/// Connection to the agent inside of a game process. The agent is injected
+/// if the process doesn't have it loaded yet and stays loaded afterwards.
+pub struct Session {
+    syringe: Syringe,
+    /// Handle of the agent module the handshake was done with. Kept as an
+    /// address so the session can be sent between threads.
+    agent_module: usize,
+    agent: AgentInfo,
+    capabilities: GameCapabilities,
+    next_request: AtomicU64,
+}
+
+impl Session {
+    /// Connects to the agent, injecting it if needed. An agent left behind by
+    /// another version of the reloader is swapped out for ours if it hasn't
+    /// hooked the game yet, otherwise the session is refused.
+    pub fn attach(process_id: u32) -> Result<Self, SessionError> {
+        let target_process = OwnedProcess::from_pid(process_id)
+            .map_err(|_| SessionError::FindingProcess)?;
+
+        // Obtain an instance of the agent DLL in the remote process
+        let syringe = Syringe::for_process(target_process);
+        let mut agent_module = syringe.find_or_inject(agent_dll())?;
+
+        let agent = match handshake(&syringe, agent_module)? {
+            Some(agent) if agent.protocol_version == PROTOCOL_VERSION => agent,
+            // Unloading a hooked agent would crash the game
+            Some(agent) if agent.hooked => {
+                return Err(SessionError::ProtocolMismatch(agent.agent_version, agent.protocol_version, PROTOCOL_VERSION));
+            },
+            // Agents from before the handshake never hook the game, so they're
+            // as safe to swap out as an unhooked agent of another version
+            _ => {
+                syringe.eject(agent_module)?;
+                agent_module = syringe.inject(agent_dll())?;
+
+                let agent = handshake(&syringe, agent_module)?
+                    .ok_or(SessionError::MissingProcedure("Handshake"))?;
+                check_protocol(agent)?
+            },
+        };
+
+        let capabilities = match send(&syringe, agent_module, Request { id: 0, operation: Operation::Capabilities })? {
+            Reply::Capabilities(capabilities) => capabilities,
+            _ => return Err(SessionError::UnexpectedReply("Capabilities")),
+        };
+
+        Ok(Self {
+            agent_module: agent_module.handle() as usize,
+            syringe,
+            agent,
+            capabilities,
+            next_request: AtomicU64::new(1),
+        })
+    }
+
+    /// What the agent reported about itself when the session was attached.
+    pub fn agent(&self) -> &AgentInfo {
+        &self.agent
+    }
+
+    fn call(&self, operation: Operation) -> Result<Reply, SessionError> {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+
+        // Requests go to the agent we did the handshake with rather than
+        // whatever is loaded under its name now
+        let agent_module = unsafe {
+            BorrowedProcessModule::new_unchecked(self.agent_module as ModuleHandle, self.syringe.process())
+        };
+
+        send(&self.syringe, agent_module, Request { id, operation })
+    }
+}
+
+impl Agent for Session {
+    fn capabilities(&self) -> &GameCapabilities {
+        &self.capabilities
+    }
+
+    fn patch(
+        &self,
+        fxrs: Vec<FxrPayload>,
+        options: &PatchOptions,
+    ) -> Result<Vec<PatchOutcome>, SessionError> {
+        require(&self.capabilities, Capability::Patch)?;
+        if options.persistent {
+            require(&self.capabilities, Capability::PersistentOverrides)?;
+        }
+
+        fxrs.iter().try_for_each(|f| check_compatibility(self.capabilities.game, &f.bytes))?;
+
+        match self.call(Operation::Patch { fxrs, options: options.clone() })? {
+            Reply::Patched(outcomes) => Ok(outcomes),
+            _ => Err(SessionError::UnexpectedReply("Patch")),
+        }
+    }
+
+    fn insert(&self, fxrs: Vec<FxrPayload>) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::Insert)?;
+
+        fxrs.iter().try_for_each(|f| check_compatibility(self.capabilities.game, &f.bytes))?;
+
+        match self.call(Operation::Insert { fxrs })? {
+            Reply::Done => Ok(()),
+            _ => Err(SessionError::UnexpectedReply("Insert")),
+        }
+    }
+
+    fn restore(&self, fxr_ids: Vec<u32>) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::Restore)?;
+
+        match self.call(Operation::Restore { fxr_ids })? {
+            Reply::Done => Ok(()),
+            _ => Err(SessionError::UnexpectedReply("Restore")),
+        }
+    }
+
+    fn resident_fxrs(&self) -> Result<Vec<u32>, SessionError> {
+        require(&self.capabilities, Capability::ListResident)?;
+
+        match self.call(Operation::ListResident)? {
+            Reply::Resident(fxr_ids) => Ok(fxr_ids),
+            _ => Err(SessionError::UnexpectedReply("ListResident")),
+        }
+    }
+
+    fn dump_fxr(&self, fxr_id: u32) -> Result<FxrDump, SessionError> {
+        require(&self.capabilities, Capability::Dump)?;
+
+        match self.call(Operation::Dump { fxr_id })? {
+            Reply::Dump(dump) => Ok(dump),
+            _ => Err(SessionError::UnexpectedReply("Dump")),
+        }
+    }
+
+    fn memory_stats(&self) -> Result<MemoryStats, SessionError> {
+        require(&self.capabilities, Capability::MemoryStats)?;
+
+        match self.call(Operation::MemoryStats)? {
+            Reply::MemoryStats(stats) => Ok(stats),
+            _ => Err(SessionError::UnexpectedReply("MemoryStats")),
+        }
+    }
+
+    fn list_overrides(&self) -> Result<Vec<OverrideEntry>, SessionError> {
+        match self.call(Operation::ListOverrides)? {
+            Reply::Overrides(overrides) => Ok(overrides),
+            _ => Err(SessionError::UnexpectedReply("ListOverrides")),
+        }
+    }
+
+    fn clear_overrides(&self, fxr_ids: Option<Vec<u32>>) -> Result<(), SessionError> {
+        match self.call(Operation::ClearOverrides { fxr_ids })? {
+            Reply::Done => Ok(()),
+            _ => Err(SessionError::UnexpectedReply("ClearOverrides")),
+        }
+    }
+
+    fn start_control_server(&self, options: ControlServerOptions) -> Result<(), SessionError> {
+        require(&self.capabilities, Capability::ControlServer)?;
+
+        match self.call(Operation::StartControlServer(options))? {
+            Reply::Done => Ok(()),
+            _ => Err(SessionError::UnexpectedReply("StartControlServer")),
+        }
+    }
+}
+
+/// Sends a request to the agent and waits for its response.
+fn send(syringe: &Syringe, agent_module: BorrowedProcessModule<'_>, request: Request) -> Result<Reply, SessionError> {
+    let remote_fn = unsafe {
+        syringe.get_payload_procedure::<fn(Request) -> Response>(agent_module, "Call")
+    }?.ok_or(SessionError::MissingProcedure("Call"))?;
+
+    let response = remote_fn.call(&request)?;
+    if response.id != request.id {
+        return Err(SessionError::MismatchedResponse(request.id, response.id));
+    }
+
+    Ok(response.result?)
+}
+
+fn agent_dll() -> String {
+    format!("{}.dll", protocol::AGENT_DLL_NAME)
+}
+
+/// Asks the agent for its version and whether it has hooked the game. Returns
+/// `None` for agents that predate the handshake. The game's capabilities are
+/// asked for separately once the protocol versions are known to match, the
+/// handshake has to stay readable across versions.
+fn handshake(syringe: &Syringe, agent_module: BorrowedProcessModule<'_>) -> Result<Option<AgentInfo>, SessionError> {
+    let remote_fn = unsafe {
+        syringe.get_payload_procedure::<fn() -> AgentInfo>(agent_module, "Handshake")
+    }?;
+
+    match remote_fn {
+        Some(remote_fn) => Ok(Some(remote_fn.call()?)),
+        None => Ok(None),
+    }
+}
+
+fn check_protocol(agent: AgentInfo) -> Result<AgentInfo, SessionError> {
+    if agent.protocol_version != PROTOCOL_VERSION {
+        return Err(SessionError::ProtocolMismatch(agent.agent_version, agent.protocol_version, PROTOCOL_VERSION));
+    }
+
+    Ok(agent)
+}
@@ -0,0 +1,68 @@
+use std::convert::Infallible;
+
+use protocol::{
+    AgentInfo, ControlServerOptions, FxrDump, FxrPayload, GameCapabilities, MemoryStats,
+    OverrideEntry, PatchOptions, PatchOutcome,
+};
+
+use crate::agent::Agent;
+use crate::SessionError;
+
+/// Stands in for the session on platforms the agent can't be injected on, so
+/// hosts still build there. Attaching always fails, which means there's never
+/// a session to call into.
+pub struct Session {
+    never: Infallible,
+}
+
+impl Session {
+    pub fn attach(_process_id: u32) -> Result<Self, SessionError> {
+        Err(SessionError::UnsupportedPlatform)
+    }
+
+    pub fn agent(&self) -> &AgentInfo {
+        match self.never {}
+    }
+}
+
+impl Agent for Session {
+    fn capabilities(&self) -> &GameCapabilities {
+        match self.never {}
+    }
+
+    fn patch(&self, _fxrs: Vec<FxrPayload>, _options: &PatchOptions) -> Result<Vec<PatchOutcome>, SessionError> {
+        match self.never {}
+    }
+
+    fn insert(&self, _fxrs: Vec<FxrPayload>) -> Result<(), SessionError> {
+        match self.never {}
+    }
+
+    fn restore(&self, _fxr_ids: Vec<u32>) -> Result<(), SessionError> {
+        match self.never {}
+    }
+
+    fn resident_fxrs(&self) -> Result<Vec<u32>, SessionError> {
+        match self.never {}
+    }
+
+    fn dump_fxr(&self, _fxr_id: u32) -> Result<FxrDump, SessionError> {
+        match self.never {}
+    }
+
+    fn memory_stats(&self) -> Result<MemoryStats, SessionError> {
+        match self.never {}
+    }
+
+    fn list_overrides(&self) -> Result<Vec<OverrideEntry>, SessionError> {
+        match self.never {}
+    }
+
+    fn clear_overrides(&self, _fxr_ids: Option<Vec<u32>>) -> Result<(), SessionError> {
+        match self.never {}
+    }
+
+    fn start_control_server(&self, _options: ControlServerOptions) -> Result<(), SessionError> {
+        match self.never {}
+    }
+}
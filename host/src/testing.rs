@@ -0,0 +1,37 @@
+//! FXRs for the tests, made from one of the `fxr` crate's fixtures.
+
+use fxr::{Field, Fxr, Header};
+use protocol::FxrPayload;
+
+/// An FXR that optionally spawns another one.
+pub(crate) fn fxr(id: u32, child: Option<u32>) -> Vec<u8> {
+    let mut fxr = Fxr::read(include_bytes!("../../fxr/tests/fixtures/aligned.fxr")).unwrap();
+    fxr.id = id;
+
+    // Elden Ring refuses FXRs that only use actions Sekiro already had
+    let mut lens_flare = fxr.actions[0].clone();
+    lens_flare.kind = 10014;
+    fxr.actions.push(lens_flare);
+
+    // Turn the fixture's only action into an SFX reference
+    if let Some(child) = child {
+        fxr.actions[0].kind = 129;
+        let first_field = fxr.actions[0].fields.unwrap() as usize;
+        fxr.fields[first_field] = Field::from_int(child as i32);
+    }
+
+    fxr.to_bytes()
+}
+
+/// An FXR whose header and actions are fine but whose last section is cut
+/// off, so only a full read notices.
+pub(crate) fn truncated_fxr(id: u32, child: Option<u32>) -> Vec<u8> {
+    let mut bytes = fxr(id, child);
+    let last = Header::read(&bytes).unwrap().section(13).unwrap().offset;
+    bytes.truncate(last as usize);
+    bytes
+}
+
+pub(crate) fn payload(bytes: Vec<u8>) -> FxrPayload {
+    FxrPayload::new(None, bytes)
+}
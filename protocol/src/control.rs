@@ -0,0 +1,278 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{AgentInfo, Operation, PatchFxrError, Reply, Request, Response, PROTOCOL_VERSION};
+
+/// Port the agent listens on for TCP control connections unless told
+/// otherwise. Only ever bound to the loopback interface.
+pub const DEFAULT_CONTROL_PORT: u16 = 24873;
+
+/// Largest frame either side accepts, far beyond any FXR the games ship.
+pub const MAX_FRAME_SIZE: usize = 0x400_0000;
+
+#[derive(Debug, Error)]
+pub enum ControlError {
+    #[error("Control connection failed. {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not encode or decode message. {0}")]
+    Encoding(#[from] serde_json::Error),
+    #[error("Message of {0} bytes exceeds the frame size limit.")]
+    FrameTooLarge(usize),
+    #[error("Connection was closed.")]
+    Closed,
+    #[error("Agent {0} speaks protocol version {1} but {2} is required.")]
+    ProtocolMismatch(String, u32, u32),
+    #[error("Agent answered request {0} with a reply for request {1}.")]
+    MismatchedResponse(u64, u64),
+    #[error("{0}")]
+    Agent(#[from] PatchFxrError),
+}
+
+/// Which control channels the agent should open. Channels that are already
+/// open stay open.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ControlServerOptions {
+    /// Listen on the named pipe for the process.
+    pub pipe: bool,
+    /// Listen on this loopback TCP port.
+    pub tcp_port: Option<u16>,
+}
+
+/// Name of the pipe the agent inside of the process listens on.
+pub fn pipe_name(process_id: u32) -> String {
+    format!(r"\\.\pipe\fxr-reloader-{process_id}")
+}
+
+/// Writes a message as a little endian length followed by its JSON.
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<(), ControlError> {
+    let bytes = serde_json::to_vec(message)?;
+    if bytes.len() > MAX_FRAME_SIZE {
+        return Err(ControlError::FrameTooLarge(bytes.len()));
+    }
+
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a message written by `write_frame`. The other side hanging up
+/// between messages is reported as `ControlError::Closed`.
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, ControlError> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(ControlError::Closed),
+        result => result?,
+    }
+
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(ControlError::FrameTooLarge(length));
+    }
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Serves a single control connection. The agent introduces itself first,
+/// after which every request is answered in order until the client hangs up.
+pub fn serve_connection<S: Read + Write>(
+    mut stream: S,
+    agent: &AgentInfo,
+    handle: impl Fn(Request) -> Response,
+) -> Result<(), ControlError> {
+    write_frame(&mut stream, agent)?;
+
+    loop {
+        let request = match read_frame::<_, Request>(&mut stream) {
+            Ok(request) => request,
+            Err(ControlError::Closed) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        write_frame(&mut stream, &handle(request))?;
+    }
+}
+
+/// Talks to the agent over one of its control channels, for tools that don't
+/// inject the agent themselves.
+pub struct ControlClient<S> {
+    stream: S,
+    agent: AgentInfo,
+    next_request: u64,
+}
+
+impl<S: Read + Write> ControlClient<S> {
+    /// Waits for the agent to introduce itself and checks that it speaks our
+    /// protocol.
+    pub fn new(mut stream: S) -> Result<Self, ControlError> {
+        let agent: AgentInfo = read_frame(&mut stream)?;
+        if agent.protocol_version != PROTOCOL_VERSION {
+            return Err(ControlError::ProtocolMismatch(agent.agent_version, agent.protocol_version, PROTOCOL_VERSION));
+        }
+
+        Ok(Self { stream, agent, next_request: 0 })
+    }
+
+    pub fn agent(&self) -> &AgentInfo {
+        &self.agent
+    }
+
+    pub fn call(&mut self, operation: Operation) -> Result<Reply, ControlError> {
+        let request = Request { id: self.next_request, operation };
+        self.next_request += 1;
+
+        write_frame(&mut self.stream, &request)?;
+        let response: Response = read_frame(&mut self.stream)?;
+        if response.id != request.id {
+            return Err(ControlError::MismatchedResponse(request.id, response.id));
+        }
+
+        Ok(response.result?)
+    }
+}
+
+impl ControlClient<TcpStream> {
+    pub fn connect_tcp(address: impl ToSocketAddrs) -> Result<Self, ControlError> {
+        Self::new(TcpStream::connect(address)?)
+    }
+}
+
+#[cfg(windows)]
+impl ControlClient<std::fs::File> {
+    /// Connects to the pipe of the agent inside of the supplied process.
+    pub fn connect_pipe(process_id: u32) -> Result<Self, ControlError> {
+        let pipe = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pipe_name(process_id))?;
+
+        Self::new(pipe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    use super::*;
+    use crate::Capability;
+
+    fn agent(protocol_version: u32) -> AgentInfo {
+        AgentInfo {
+            protocol_version,
+            hooked: false,
+            agent_version: String::from("test"),
+        }
+    }
+
+    /// Answers like an agent that has FXR 300 loaded and nothing else.
+    fn handle(request: Request) -> Response {
+        let result = match request.operation {
+            Operation::ListResident => Ok(Reply::Resident(vec![300])),
+            Operation::Dump { fxr_id: 300 } => Ok(Reply::Dump(crate::FxrDump {
+                address: 0x1000,
+                bytes: vec![1, 2, 3],
+            })),
+            Operation::Dump { fxr_id } => Err(PatchFxrError::NotLoaded(fxr_id)),
+            _ => Err(PatchFxrError::Unsupported(Capability::Patch)),
+        };
+
+        Response { id: request.id, result }
+    }
+
+    /// Serves a single connection on an ephemeral loopback port.
+    fn serve_once(agent: AgentInfo, handle: fn(Request) -> Response) -> (u16, thread::JoinHandle<Result<(), ControlError>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            serve_connection(stream, &agent, handle)
+        });
+
+        (port, server)
+    }
+
+    #[test]
+    fn client_talks_to_server_over_loopback() {
+        let (port, server) = serve_once(agent(PROTOCOL_VERSION), handle);
+
+        let mut client = ControlClient::connect_tcp((Ipv4Addr::LOCALHOST, port)).unwrap();
+        assert_eq!(client.agent().agent_version, "test");
+
+        assert!(matches!(client.call(Operation::ListResident), Ok(Reply::Resident(ids)) if ids == [300]));
+        assert!(matches!(
+            client.call(Operation::Dump { fxr_id: 300 }),
+            Ok(Reply::Dump(dump)) if dump.address == 0x1000 && dump.bytes == [1, 2, 3]
+        ));
+        assert!(matches!(
+            client.call(Operation::Dump { fxr_id: 301 }),
+            Err(ControlError::Agent(PatchFxrError::NotLoaded(301)))
+        ));
+
+        // Hanging up ends the connection without an error
+        drop(client);
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn client_refuses_other_protocol_versions() {
+        let (port, _) = serve_once(agent(PROTOCOL_VERSION + 1), handle);
+
+        let result = ControlClient::connect_tcp((Ipv4Addr::LOCALHOST, port));
+        assert!(matches!(
+            result,
+            Err(ControlError::ProtocolMismatch(_, version, PROTOCOL_VERSION)) if version == PROTOCOL_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn client_refuses_responses_to_other_requests() {
+        let (port, _) = serve_once(agent(PROTOCOL_VERSION), |request| Response {
+            id: request.id + 1,
+            result: Ok(Reply::Done),
+        });
+
+        let mut client = ControlClient::connect_tcp((Ipv4Addr::LOCALHOST, port)).unwrap();
+        assert!(matches!(client.call(Operation::MemoryStats), Err(ControlError::MismatchedResponse(0, 1))));
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &Request { id: 7, operation: Operation::Restore { fxr_ids: vec![1, 2] } }).unwrap();
+        assert_eq!(u32::from_le_bytes(buffer[..4].try_into().unwrap()) as usize, buffer.len() - 4);
+
+        let request: Request = read_frame(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(request.id, 7);
+        assert!(matches!(request.operation, Operation::Restore { fxr_ids } if fxr_ids == [1, 2]));
+    }
+
+    #[test]
+    fn oversized_frames_are_refused_before_reading_them() {
+        let length = (MAX_FRAME_SIZE as u32 + 1).to_le_bytes();
+
+        let result = read_frame::<_, Request>(&mut Cursor::new(length));
+        assert!(matches!(result, Err(ControlError::FrameTooLarge(size)) if size == MAX_FRAME_SIZE + 1));
+    }
+
+    #[test]
+    fn hanging_up_between_frames_is_reported_as_closed() {
+        let result = read_frame::<_, Request>(&mut Cursor::new(Vec::new()));
+        assert!(matches!(result, Err(ControlError::Closed)));
+
+        // Hanging up halfway through a frame is an actual error
+        let result = read_frame::<_, Request>(&mut Cursor::new(vec![8, 0, 0, 0, b'{']));
+        assert!(matches!(result, Err(ControlError::Io(_))));
+    }
+}
@@ -1,6 +1,15 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+mod control;
+mod message;
+
+pub use control::{
+    pipe_name, read_frame, serve_connection, write_frame, ControlClient, ControlError,
+    ControlServerOptions, DEFAULT_CONTROL_PORT, MAX_FRAME_SIZE,
+};
+pub use message::{FxrPayload, Operation, Reply, Request, Response};
+
 #[derive(Debug, Error, Serialize, Deserialize)]
 pub enum PatchFxrError {
     #[error("Could not locate CSSfx singleton. {0}")]
@@ -13,6 +22,30 @@ pub enum PatchFxrError {
     GameDetectionError(#[from] GameDetectionError),
     #[error("Could not match pattern instructions. {0}")]
     InstructionPattern(String),
+    #[error("Could not hook the SFX update. {0}")]
+    Hook(String),
+    #[error("Timed out waiting for the game to swap in FXR {0}.")]
+    SwapTimeout(u32),
+    #[error("FXR {0} got unloaded before it could be swapped in.")]
+    SwapFailed(u32),
+    #[error("FXR {0} was not patched by the reloader.")]
+    NotPatched(u32),
+    #[error("FXR {0} is already loaded and can't be inserted, patch it instead.")]
+    AlreadyLoaded(u32),
+    #[error("FXR {0} is not loaded by the game.")]
+    NotLoaded(u32),
+    #[error("{0} is not supported in this game.")]
+    Unsupported(Capability),
+    #[error("Could not get hold of the game's FXR allocator.")]
+    AllocatorUnavailable,
+    #[error("Could not start the control server. {0}")]
+    ControlServer(String),
+    #[error("{0} got corrupted on the way to the agent.")]
+    CorruptPayload(String),
+    #[error("Could not follow the pointers from CSSfx to the FXR resource list.")]
+    ResourceListMissing,
+    #[error("FXR resource list loops back on itself at {0:#x}.")]
+    ResourceListCycle(usize),
 }
 
 #[derive(Debug, Error, Serialize, Deserialize)]
@@ -53,10 +86,167 @@ pub enum GameDetectionError {
     MissingPEStringsLanguage,
     #[error("Failed acquiring product name from PE header. Cannot determine what game we're running.")]
     MissingProductName,
+    #[error("Failed acquiring product version from PE header.")]
+    MissingProductVersion,
     #[error("Did not recognize game for product name {0}.")]
     UnknownProductName(String),
     #[error("Failed to find the .text section.")]
     MissingTextSection,
 }
 
+/// The game the agent was injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunningGame {
+    EldenRing,
+    // ArmoredCore6,
+}
+
+impl std::fmt::Display for RunningGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::EldenRing => "Elden Ring",
+            // Self::ArmoredCore6 => "Armored Core 6",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PatchOptions {
+    /// Keep swapping the FXR in whenever the game reloads it from the
+    /// archives, until the override is cleared or the FXR is restored.
+    #[serde(default)]
+    pub persistent: bool,
+    /// Go through everything up to swapping in the definition and report
+    /// what would happen, without touching the game.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// An FXR the agent holds on to, either because the game hasn't loaded it yet
+/// or because it's a persistent override.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverrideEntry {
+    pub fxr_id: u32,
+    pub persistent: bool,
+    /// Size of the FXR in bytes.
+    pub size: usize,
+}
+
+/// What happened to an FXR that was sent over for patching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatchOutcome {
+    /// The FXR was loaded and its definition has been swapped.
+    Patched(u32),
+    /// The FXR is not loaded right now. It will be swapped in as soon as the
+    /// game loads it. Only happens when the agent hooked the SFX update,
+    /// otherwise patching an FXR that isn't loaded fails.
+    Pending(u32),
+    /// Dry run, the FXR is loaded and its definition would've been swapped.
+    WouldPatch(u32),
+    /// Dry run, the FXR is not loaded and would've been held on to until the
+    /// game loads it.
+    WouldQueue(u32),
+}
+
+/// Memory the agent holds inside of the game for the FXRs it swapped in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MemoryStats {
+    /// Amount of FXRs currently pointing at a definition allocated by the agent.
+    pub tracked_fxrs: usize,
+    /// Bytes held by definitions that are currently swapped in.
+    pub live_bytes: usize,
+    /// Bytes held by superseded definitions that are waiting to be freed.
+    pub retired_bytes: usize,
+}
+
+/// Copy of an FXR definition as the game has it loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FxrDump {
+    /// Where the definition lives in the game. The game relocated the
+    /// definition's offsets against this address.
+    pub address: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// What the agent reports about itself when the host connects to it.
+///
+/// The handshake is how a host finds out it's talking to an agent of another
+/// version, so unlike everything else passed between the two this layout
+/// can't change with `PROTOCOL_VERSION`. The handshake is bincode encoded,
+/// which goes by field order: the protocol version comes first and fields may
+/// only ever be added at the end. Anything that does depend on the version,
+/// like the game's capabilities, is asked for once the versions match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentInfo {
+    /// The `PROTOCOL_VERSION` the agent was built against.
+    pub protocol_version: u32,
+    /// Whether the agent has hooked the game. A hooked agent can't be
+    /// unloaded without crashing the game.
+    pub hooked: bool,
+    /// Version of the agent DLL.
+    pub agent_version: String,
+}
+
+/// Operations an agent can perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Capability {
+    Patch,
+    Insert,
+    Restore,
+    ListResident,
+    Dump,
+    PersistentOverrides,
+    MemoryStats,
+    ControlServer,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 8] = [
+        Self::Patch,
+        Self::Insert,
+        Self::Restore,
+        Self::ListResident,
+        Self::Dump,
+        Self::PersistentOverrides,
+        Self::MemoryStats,
+        Self::ControlServer,
+    ];
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Patch => "Patching FXRs",
+            Self::Insert => "Inserting FXRs",
+            Self::Restore => "Restoring FXRs",
+            Self::ListResident => "Listing loaded FXRs",
+            Self::Dump => "Dumping FXRs",
+            Self::PersistentOverrides => "Persistent overrides",
+            Self::MemoryStats => "Memory stats",
+            Self::ControlServer => "Control server",
+        })
+    }
+}
+
+/// What the agent can do in the game it was injected into. Depends on the
+/// game, its version and whether the agent managed to hook it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameCapabilities {
+    pub game: RunningGame,
+    /// Version of the game executable.
+    pub version: String,
+    pub capabilities: Vec<Capability>,
+}
+
+impl GameCapabilities {
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Bumped whenever a procedure's signature or one of the types passed between
+/// the host and the agent changes. A host only talks to an agent with the same
+/// protocol version.
+pub const PROTOCOL_VERSION: u32 = 9;
+
 pub const AGENT_DLL_NAME: &str = "fxr_reloader_agent";
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ControlServerOptions, FxrDump, GameCapabilities, MemoryStats, OverrideEntry, PatchFxrError, PatchOptions,
+    PatchOutcome,
+};
+
+/// A call into the agent. Every operation goes through the same procedure,
+/// the ID is echoed back in the response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub operation: Operation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Operation {
+    Capabilities,
+    Patch {
+        fxrs: Vec<FxrPayload>,
+        options: PatchOptions,
+    },
+    Insert {
+        fxrs: Vec<FxrPayload>,
+    },
+    Restore {
+        fxr_ids: Vec<u32>,
+    },
+    ListResident,
+    Dump {
+        fxr_id: u32,
+    },
+    MemoryStats,
+    ListOverrides,
+    /// Stops overriding the listed FXRs, or all of them if `None`.
+    ClearOverrides {
+        fxr_ids: Option<Vec<u32>>,
+    },
+    /// Opens the agent's control channels so other tools can send requests
+    /// without injecting anything.
+    StartControlServer(ControlServerOptions),
+}
+
+/// An FXR sent over to the agent along with where it came from, so errors
+/// can point at the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FxrPayload {
+    /// File the FXR was read from, if any.
+    pub source: Option<String>,
+    /// FNV-1a hash of the bytes, checked by the agent before using them.
+    pub hash: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl FxrPayload {
+    pub fn new(source: Option<String>, bytes: Vec<u8>) -> Self {
+        Self {
+            source,
+            hash: fnv1a(&bytes),
+            bytes,
+        }
+    }
+
+    /// Whether the bytes still match the hash they were sent with.
+    pub fn is_intact(&self) -> bool {
+        fnv1a(&self.bytes) == self.hash
+    }
+
+    /// Names the payload in errors, falling back to a placeholder for FXRs
+    /// that didn't come from a file.
+    pub fn describe(&self) -> String {
+        self.source.clone().unwrap_or_else(|| String::from("<unnamed FXR>"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    /// ID of the request this answers.
+    pub id: u64,
+    pub result: Result<Reply, PatchFxrError>,
+}
+
+/// The result of an operation, one variant per kind of data returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Reply {
+    Capabilities(GameCapabilities),
+    Patched(Vec<PatchOutcome>),
+    Resident(Vec<u32>),
+    Dump(FxrDump),
+    MemoryStats(MemoryStats),
+    Overrides(Vec<OverrideEntry>),
+    /// The operation doesn't return anything.
+    Done,
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Capability, RunningGame};
+
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(message: &T) -> T {
+        serde_json::from_slice(&serde_json::to_vec(message).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let request = Request {
+            id: 7,
+            operation: Operation::Patch {
+                fxrs: vec![FxrPayload::new(Some(String::from("300.fxr")), vec![1, 2, 3])],
+                options: PatchOptions { persistent: true, dry_run: false },
+            },
+        };
+
+        let Request { id, operation: Operation::Patch { fxrs, options } } = round_trip(&request) else {
+            panic!("request came back as another operation");
+        };
+        assert_eq!(id, 7);
+        assert_eq!(fxrs[0].source.as_deref(), Some("300.fxr"));
+        assert_eq!(fxrs[0].bytes, [1, 2, 3]);
+        assert!(fxrs[0].is_intact());
+        assert!(options.persistent && !options.dry_run);
+
+        let request = Request { id: 8, operation: Operation::ClearOverrides { fxr_ids: None } };
+        assert!(matches!(round_trip(&request).operation, Operation::ClearOverrides { fxr_ids: None }));
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let response = Response {
+            id: 7,
+            result: Ok(Reply::Patched(vec![PatchOutcome::Patched(300), PatchOutcome::Pending(301)])),
+        };
+        let decoded = round_trip(&response);
+        assert_eq!(decoded.id, 7);
+        assert!(matches!(decoded.result, Ok(Reply::Patched(o)) if o == [PatchOutcome::Patched(300), PatchOutcome::Pending(301)]));
+
+        let response = Response {
+            id: 8,
+            result: Ok(Reply::Capabilities(GameCapabilities {
+                game: RunningGame::EldenRing,
+                version: String::from("2.2.0.0"),
+                capabilities: vec![Capability::Patch],
+            })),
+        };
+        assert!(matches!(round_trip(&response).result, Ok(Reply::Capabilities(c)) if c.capabilities == [Capability::Patch]));
+
+        let response = Response { id: 9, result: Err(PatchFxrError::NotLoaded(300)) };
+        assert!(matches!(round_trip(&response).result, Err(PatchFxrError::NotLoaded(300))));
+    }
+
+    #[test]
+    fn patch_options_default_to_off() {
+        let request = r#"{"id":1,"operation":{"Patch":{"fxrs":[],"options":{}}}}"#;
+        let Operation::Patch { options, .. } = serde_json::from_str::<Request>(request).unwrap().operation else {
+            panic!("request came back as another operation");
+        };
+        assert!(!options.persistent && !options.dry_run);
+
+        let options: PatchOptions = serde_json::from_str(r#"{"dry_run":true}"#).unwrap();
+        assert!(!options.persistent && options.dry_run);
+    }
+
+    #[test]
+    fn payloads_catch_changed_bytes() {
+        let payload = FxrPayload::new(None, vec![1, 2, 3]);
+        assert!(payload.is_intact());
+        assert!(round_trip(&payload).is_intact());
+
+        let mut changed = payload.clone();
+        changed.bytes[1] = 0xff;
+        assert!(!changed.is_intact());
+        assert!(!round_trip(&changed).is_intact());
+
+        let mut truncated = payload;
+        truncated.bytes.pop();
+        assert!(!truncated.is_intact());
+        assert_eq!(truncated.describe(), "<unnamed FXR>");
+    }
+}
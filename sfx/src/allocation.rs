@@ -0,0 +1,250 @@
+use std::mem;
+use std::collections;
+use protocol::MemoryStats;
+
+use crate::resources::LinkedFxr;
+
+/// A block handed out by the game's allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub address: usize,
+    pub size: usize,
+}
+
+/// How an FXR we swapped a definition into got into the resource list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// The game loaded it, with this definition. The definition isn't ours
+    /// so it's never freed.
+    Loaded(usize),
+    /// We linked it in ourselves.
+    Inserted(LinkedFxr),
+}
+
+#[derive(Debug)]
+struct TrackedFxr {
+    origin: Origin,
+    /// Our definition the wrapper currently points at.
+    current: Allocation,
+}
+
+/// Bookkeeping for the definitions we swapped in. Superseded definitions are
+/// retired rather than freed on the spot since the game might still be reading
+/// them during the frame we swapped on.
+#[derive(Debug)]
+pub struct AllocationTracker {
+    tracked: collections::BTreeMap<u32, TrackedFxr>,
+    retired: Vec<Allocation>,
+}
+
+impl Default for AllocationTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AllocationTracker {
+    pub const fn new() -> Self {
+        Self {
+            tracked: collections::BTreeMap::new(),
+            retired: Vec::new(),
+        }
+    }
+
+    pub fn is_tracked(&self, fxr_id: u32) -> bool {
+        self.tracked.contains_key(&fxr_id)
+    }
+
+    /// The definition we last swapped in for an FXR.
+    pub fn current(&self, fxr_id: u32) -> Option<usize> {
+        self.tracked.get(&fxr_id).map(|t| t.current.address)
+    }
+
+    /// Records that the wrapper for `fxr_id` went from pointing at `previous`
+    /// to pointing at `allocation`.
+    pub fn replaced(&mut self, fxr_id: u32, previous: usize, allocation: Allocation) {
+        match self.tracked.get_mut(&fxr_id) {
+            // We're superseding one of our own definitions
+            Some(tracked) if tracked.current.address == previous => {
+                let superseded = mem::replace(&mut tracked.current, allocation);
+                self.retired.push(superseded);
+            },
+            // Either a first time patch or the game reloaded the FXR since we
+            // last touched it. In the latter case the game already disposed
+            // of our definition along with the old wrapper.
+            _ => {
+                self.tracked.insert(fxr_id, TrackedFxr {
+                    origin: Origin::Loaded(previous),
+                    current: allocation,
+                });
+            },
+        }
+    }
+
+    /// Records that we linked in `fxr_id` ourselves, pointing at
+    /// `allocation`.
+    pub fn inserted(&mut self, fxr_id: u32, linked: LinkedFxr, allocation: Allocation) {
+        self.tracked.insert(fxr_id, TrackedFxr {
+            origin: Origin::Inserted(linked),
+            current: allocation,
+        });
+    }
+
+    /// Stops tracking `fxr_id` and yields how it got loaded, retiring our
+    /// definition. The caller puts back the original definition, or unlinks
+    /// the node of an inserted FXR whose node and wrapper are retired along
+    /// with it. Yields nothing if the wrapper (`current`) no longer points at
+    /// our definition, there's nothing to restore then.
+    pub fn restored(&mut self, fxr_id: u32, current: usize) -> Option<Origin> {
+        let tracked = self.tracked.remove(&fxr_id)?;
+        if tracked.current.address != current {
+            return None;
+        }
+
+        self.retired.push(tracked.current);
+        if let Origin::Inserted(linked) = tracked.origin {
+            self.retired.extend([linked.node, linked.wrapper]);
+        }
+
+        Some(tracked.origin)
+    }
+
+    /// Drops an FXR that the game unloaded. Whatever we had allocated for it
+    /// went with it.
+    pub fn forget(&mut self, fxr_id: u32) {
+        self.tracked.remove(&fxr_id);
+    }
+
+    /// Marks an allocation as unreferenced so it's freed along with the
+    /// superseded definitions.
+    pub fn retire(&mut self, allocation: Allocation) {
+        self.retired.push(allocation);
+    }
+
+    /// Takes all retired allocations. The caller is responsible for freeing
+    /// them.
+    pub fn take_retired(&mut self) -> Vec<Allocation> {
+        mem::take(&mut self.retired)
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            tracked_fxrs: self.tracked.len(),
+            live_bytes: self.tracked.values().map(|t| t.current.size).sum(),
+            retired_bytes: self.retired.iter().map(|a| a.size).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: usize = 0x1000;
+
+    fn allocation(address: usize) -> Allocation {
+        Allocation { address, size: 0x100 }
+    }
+
+    #[test]
+    fn first_patch_remembers_the_original() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+
+        assert!(tracker.is_tracked(300));
+        assert_eq!(tracker.current(300), Some(0x2000));
+        assert!(tracker.take_retired().is_empty());
+        assert_eq!(tracker.restored(300, 0x2000), Some(Origin::Loaded(ORIGINAL)));
+    }
+
+    #[test]
+    fn superseding_our_own_definition_retires_it() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+        tracker.replaced(300, 0x2000, allocation(0x3000));
+
+        assert_eq!(tracker.current(300), Some(0x3000));
+        assert_eq!(tracker.take_retired(), [allocation(0x2000)]);
+
+        // The original is still the game's first definition
+        assert_eq!(tracker.restored(300, 0x3000), Some(Origin::Loaded(ORIGINAL)));
+    }
+
+    #[test]
+    fn patching_after_a_reload_starts_over() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+
+        // The game reloaded the FXR and freed ours along with its wrapper
+        tracker.replaced(300, 0x4000, allocation(0x3000));
+
+        assert!(tracker.take_retired().is_empty());
+        assert_eq!(tracker.restored(300, 0x3000), Some(Origin::Loaded(0x4000)));
+    }
+
+    #[test]
+    fn restoring_retires_ours() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+
+        assert_eq!(tracker.restored(300, 0x2000), Some(Origin::Loaded(ORIGINAL)));
+        assert!(!tracker.is_tracked(300));
+        assert_eq!(tracker.take_retired(), [allocation(0x2000)]);
+        assert_eq!(tracker.restored(300, 0x2000), None);
+    }
+
+    #[test]
+    fn restoring_an_insert_retires_its_node_and_wrapper() {
+        let mut tracker = AllocationTracker::new();
+        let linked = LinkedFxr { node: allocation(0x3000), wrapper: allocation(0x4000) };
+        tracker.inserted(400, linked, allocation(0x2000));
+
+        assert!(tracker.is_tracked(400));
+        assert_eq!(tracker.restored(400, 0x2000), Some(Origin::Inserted(linked)));
+        assert_eq!(tracker.take_retired(), [allocation(0x2000), linked.node, linked.wrapper]);
+    }
+
+    #[test]
+    fn restoring_after_a_reload_has_nothing_to_put_back() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+
+        assert_eq!(tracker.restored(300, 0x4000), None);
+        assert!(!tracker.is_tracked(300));
+        assert!(tracker.take_retired().is_empty());
+    }
+
+    #[test]
+    fn forgetting_drops_the_fxr_without_retiring() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+        tracker.forget(300);
+
+        assert!(!tracker.is_tracked(300));
+        assert_eq!(tracker.current(300), None);
+        assert!(tracker.take_retired().is_empty());
+    }
+
+    #[test]
+    fn retired_allocations_are_handed_out_once() {
+        let mut tracker = AllocationTracker::new();
+        tracker.retire(allocation(0x2000));
+        tracker.retire(allocation(0x3000));
+
+        assert_eq!(tracker.take_retired(), [allocation(0x2000), allocation(0x3000)]);
+        assert!(tracker.take_retired().is_empty());
+    }
+
+    #[test]
+    fn stats_count_live_and_retired_bytes() {
+        let mut tracker = AllocationTracker::new();
+        tracker.replaced(300, ORIGINAL, allocation(0x2000));
+        tracker.replaced(301, ORIGINAL, Allocation { address: 0x3000, size: 0x40 });
+        tracker.replaced(300, 0x2000, allocation(0x4000));
+
+        let stats = tracker.stats();
+        assert_eq!(stats.tracked_fxrs, 2);
+        assert_eq!(stats.live_bytes, 0x140);
+        assert_eq!(stats.retired_bytes, 0x100);
+    }
+}
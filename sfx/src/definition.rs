@@ -0,0 +1,57 @@
+use fxr::Header;
+use protocol::PatchFxrError;
+
+use crate::memory::Memory;
+
+/// Largest header an FXR can have, enough to tell how big the rest is.
+const MAX_HEADER_SIZE: usize = 0x90;
+
+/// Copies a loaded FXR definition out of the game's memory, up to the end of
+/// its last section.
+pub fn copy_definition<M: Memory + ?Sized>(memory: &M, definition: usize) -> Result<Vec<u8>, PatchFxrError> {
+    let mut header = [0; MAX_HEADER_SIZE];
+    if !memory.read(definition, &mut header) {
+        return Err(PatchFxrError::InvalidFxr);
+    }
+
+    let extent = Header::read(&header)
+        .map_err(|_| PatchFxrError::InvalidFxr)?
+        .extent();
+
+    let mut bytes = vec![0; extent];
+    match memory.read(definition, &mut bytes) {
+        true => Ok(bytes),
+        false => Err(PatchFxrError::InvalidFxr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::Heap;
+    use crate::memory::Allocator;
+
+    #[test]
+    fn copies_up_to_the_end_of_the_header() {
+        let heap = Heap::default();
+        let definition = heap.allocate(MAX_HEADER_SIZE + 0x20, 0x10);
+
+        // A version 5 FXR without any records ends with its header
+        heap.write(definition, b"FXR\0");
+        heap.write(definition + 0x6, &5u16.to_le_bytes());
+        heap.write_u32(definition + 0xc, 300);
+
+        let bytes = copy_definition(&heap, definition).unwrap();
+        assert_eq!(bytes.len(), MAX_HEADER_SIZE);
+        assert_eq!(Header::read(&bytes).unwrap().id, 300);
+    }
+
+    #[test]
+    fn refuses_what_isnt_an_fxr() {
+        let heap = Heap::default();
+        let definition = heap.allocate(MAX_HEADER_SIZE, 0x10);
+
+        assert!(matches!(copy_definition(&heap, definition), Err(PatchFxrError::InvalidFxr)));
+        assert!(matches!(copy_definition(&heap, 0), Err(PatchFxrError::InvalidFxr)));
+    }
+}
@@ -0,0 +1,169 @@
+//! A fake game heap backed by a `Vec<u8>`, for running the resource list
+//! logic outside of the game.
+
+use std::sync;
+
+use crate::memory::{Allocator, Memory};
+use crate::resources::{
+    ResourceLayout, ResourceList, CONTAINER_LIST_HEAD, CONTAINER_RESOURCE_COUNT, NODE_ID, NODE_NEXT,
+    NODE_PREV, NODE_SIZE, NODE_WRAPPER, RESOURCE_MANAGER_CONTAINER, SCENE_CTRL_RESOURCE_MANAGER,
+    WRAPPER_DEFINITION, WRAPPER_SIZE,
+};
+
+/// Where the heap starts, so address 0 stays unreadable like in the game.
+const BASE: usize = 0x1000;
+
+/// Size of the fake definitions, they're never parsed.
+pub(crate) const DEFINITION_SIZE: usize = 0x10;
+
+pub(crate) const LAYOUT: ResourceLayout = ResourceLayout { scene_ctrl: 0x60 };
+
+#[derive(Debug, Default)]
+pub(crate) struct Heap {
+    bytes: sync::Mutex<Vec<u8>>,
+    /// Allocations left before the allocator runs dry, unlimited if `None`.
+    remaining: sync::Mutex<Option<usize>>,
+    freed: sync::Mutex<Vec<usize>>,
+}
+
+impl Heap {
+    /// Makes every allocation after the next `count` fail.
+    pub(crate) fn run_dry_after(&self, count: usize) {
+        *self.remaining.lock().unwrap() = Some(count);
+    }
+
+    /// Everything handed to `free` so far, in order.
+    pub(crate) fn freed(&self) -> Vec<usize> {
+        self.freed.lock().unwrap().clone()
+    }
+}
+
+impl Memory for Heap {
+    fn read(&self, address: usize, buffer: &mut [u8]) -> bool {
+        let heap = self.bytes.lock().unwrap();
+        let Some(start) = address.checked_sub(BASE) else {
+            return false;
+        };
+
+        match heap.get(start..start + buffer.len()) {
+            Some(bytes) => {
+                buffer.copy_from_slice(bytes);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn write(&self, address: usize, bytes: &[u8]) -> bool {
+        let mut heap = self.bytes.lock().unwrap();
+        let Some(start) = address.checked_sub(BASE) else {
+            return false;
+        };
+
+        match heap.get_mut(start..start + bytes.len()) {
+            Some(target) => {
+                target.copy_from_slice(bytes);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Bumps through the heap and never hands anything back out.
+impl Allocator for Heap {
+    fn allocate(&self, size: usize, alignment: usize) -> usize {
+        if let Some(remaining) = self.remaining.lock().unwrap().as_mut() {
+            match remaining.checked_sub(1) {
+                Some(left) => *remaining = left,
+                None => return 0,
+            }
+        }
+
+        let mut heap = self.bytes.lock().unwrap();
+        let start = heap.len().next_multiple_of(alignment);
+        heap.resize(start + size, 0);
+
+        BASE + start
+    }
+
+    fn free(&self, address: usize) {
+        self.freed.lock().unwrap().push(address);
+    }
+}
+
+/// CSSfx with the pointers down to a resource list holding a node for every
+/// FXR, each with a definition of its own.
+pub(crate) struct FakeSfx {
+    pub(crate) heap: Heap,
+    pub(crate) sfx: usize,
+    pub(crate) container: usize,
+    pub(crate) head: usize,
+    pub(crate) nodes: Vec<usize>,
+}
+
+impl FakeSfx {
+    pub(crate) fn new(fxr_ids: &[u32]) -> Self {
+        let heap = Heap::default();
+
+        let sfx = heap.allocate(LAYOUT.scene_ctrl + 0x8, 0x8);
+        let scene_ctrl = heap.allocate(SCENE_CTRL_RESOURCE_MANAGER + 0x8, 0x8);
+        let manager = heap.allocate(RESOURCE_MANAGER_CONTAINER + 0x8, 0x8);
+        let container = heap.allocate(CONTAINER_RESOURCE_COUNT + 0x8, 0x8);
+        heap.write_usize(sfx + LAYOUT.scene_ctrl, scene_ctrl);
+        heap.write_usize(scene_ctrl + SCENE_CTRL_RESOURCE_MANAGER, manager);
+        heap.write_usize(manager + RESOURCE_MANAGER_CONTAINER, container);
+
+        // The sentinel starts out linked to itself
+        let head = heap.allocate(NODE_SIZE, 0x8);
+        heap.write_usize(head + NODE_NEXT, head);
+        heap.write_usize(head + NODE_PREV, head);
+        heap.write_usize(container + CONTAINER_LIST_HEAD, head);
+
+        let mut nodes = Vec::new();
+        let mut tail = head;
+        for &fxr_id in fxr_ids {
+            let definition = heap.allocate(DEFINITION_SIZE, 0x10);
+            let wrapper = heap.allocate(WRAPPER_SIZE, 0x8);
+            heap.write_usize(wrapper + WRAPPER_DEFINITION, definition);
+
+            let node = heap.allocate(NODE_SIZE, 0x8);
+            heap.write_usize(node + NODE_NEXT, head);
+            heap.write_usize(node + NODE_PREV, tail);
+            heap.write_u32(node + NODE_ID, fxr_id);
+            heap.write_usize(node + NODE_WRAPPER, wrapper);
+            heap.write_usize(tail + NODE_NEXT, node);
+            heap.write_usize(head + NODE_PREV, node);
+
+            nodes.push(node);
+            tail = node;
+        }
+        heap.write_usize(container + CONTAINER_RESOURCE_COUNT, fxr_ids.len());
+
+        Self { heap, sfx, container, head, nodes }
+    }
+
+    pub(crate) fn list(&self) -> ResourceList<'_, Heap> {
+        ResourceList::locate(&self.heap, self.sfx, &LAYOUT).unwrap()
+    }
+
+    /// The definition the game currently has loaded for an FXR.
+    pub(crate) fn definition(&self, fxr_id: u32) -> Option<usize> {
+        let slot = self.list().definition_slot(fxr_id).unwrap()?;
+        self.heap.read_usize(slot)
+    }
+
+    pub(crate) fn resource_count(&self) -> usize {
+        self.heap.read_usize(self.container + CONTAINER_RESOURCE_COUNT).unwrap()
+    }
+
+    /// Has the game swap in a fresh definition of its own for an FXR, like
+    /// it does when it reloads one.
+    pub(crate) fn reload(&self, fxr_id: u32) -> usize {
+        let slot = self.list().definition_slot(fxr_id).unwrap().unwrap();
+        let definition = self.heap.allocate(DEFINITION_SIZE, 0x10);
+        self.heap.write_usize(slot, definition);
+
+        definition
+    }
+}
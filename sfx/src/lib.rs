@@ -0,0 +1,13 @@
+mod allocation;
+mod definition;
+#[cfg(test)]
+mod heap;
+mod memory;
+mod resources;
+mod swap;
+
+pub use allocation::{Allocation, AllocationTracker, Origin};
+pub use definition::copy_definition;
+pub use memory::{Allocator, Memory};
+pub use resources::{LinkedFxr, ResidentFxr, ResourceLayout, ResourceList};
+pub use swap::{apply_swap, PendingSwap, SwapKind, SwapQueue, SwapState, SwapTicket};
@@ -0,0 +1,44 @@
+/// Access to the memory the game's structures live in. The game's structures
+/// are walked through this rather than through raw pointers, which lets the
+/// same logic run against a fake heap outside of the game.
+pub trait Memory: Send + Sync {
+    /// Fills `buffer` with the bytes at `address`. Returns false if the
+    /// address can't be read.
+    fn read(&self, address: usize, buffer: &mut [u8]) -> bool;
+
+    /// Overwrites the bytes at `address`. Returns false if the address can't
+    /// be written.
+    fn write(&self, address: usize, bytes: &[u8]) -> bool;
+
+    fn read_u32(&self, address: usize) -> Option<u32> {
+        let mut buffer = [0; 4];
+        self.read(address, &mut buffer).then(|| u32::from_le_bytes(buffer))
+    }
+
+    fn read_usize(&self, address: usize) -> Option<usize> {
+        let mut buffer = [0; 8];
+        self.read(address, &mut buffer).then(|| u64::from_le_bytes(buffer) as usize)
+    }
+
+    fn write_u32(&self, address: usize, value: u32) -> bool {
+        self.write(address, &value.to_le_bytes())
+    }
+
+    fn write_usize(&self, address: usize, value: usize) -> bool {
+        self.write(address, &(value as u64).to_le_bytes())
+    }
+
+    /// Follows a pointer stored at `address`. Null pointers are treated like
+    /// unreadable ones.
+    fn read_pointer(&self, address: usize) -> Option<usize> {
+        self.read_usize(address).filter(|p| *p != 0)
+    }
+}
+
+/// The allocator the game hands out its FXR definitions and resource list
+/// entries from.
+pub trait Allocator {
+    fn allocate(&self, size: usize, alignment: usize) -> usize;
+
+    fn free(&self, address: usize);
+}
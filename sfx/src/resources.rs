@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+
+use protocol::PatchFxrError;
+
+use crate::allocation::Allocation;
+use crate::memory::{Allocator, Memory};
+
+/// Where the scene control sits in CSSfx, the only part of the path to the
+/// resource list that differs between games.
+#[derive(Debug)]
+pub struct ResourceLayout {
+    pub scene_ctrl: usize,
+}
+
+/// GXFfxSceneCtrl::graphics_resource_manager
+pub(crate) const SCENE_CTRL_RESOURCE_MANAGER: usize = 0x28;
+/// GXFfxGraphicsResourceManager::resource_container
+pub(crate) const RESOURCE_MANAGER_CONTAINER: usize = 0x160;
+
+/// FxrResourceContainer::fxr_list_head
+pub(crate) const CONTAINER_LIST_HEAD: usize = 0x20;
+/// FxrResourceContainer::resource_count
+pub(crate) const CONTAINER_RESOURCE_COUNT: usize = 0x28;
+
+/// FxrListNode: next, prev, id, padding and the wrapper.
+pub(crate) const NODE_NEXT: usize = 0x0;
+pub(crate) const NODE_PREV: usize = 0x8;
+pub(crate) const NODE_ID: usize = 0x10;
+pub(crate) const NODE_WRAPPER: usize = 0x18;
+pub(crate) const NODE_SIZE: usize = 0x20;
+
+/// FxrWrapper: the definition followed by something we leave zeroed.
+pub(crate) const WRAPPER_DEFINITION: usize = 0x0;
+pub(crate) const WRAPPER_SIZE: usize = 0x10;
+
+/// The game's list of loaded FXRs, reached through CSSfx, GXFfxSceneCtrl,
+/// GXFfxGraphicsResourceManager and finally FxrResourceContainer. The list
+/// is circular and doubly linked with a sentinel head that has ID 0.
+pub struct ResourceList<'a, M: Memory + ?Sized> {
+    memory: &'a M,
+    container: usize,
+    /// Null until the game has set up the list.
+    head: Option<usize>,
+}
+
+impl<'a, M: Memory + ?Sized> ResourceList<'a, M> {
+    /// Follows the pointers from the CSSfx instance at `sfx` down to the
+    /// resource container.
+    pub fn locate(memory: &'a M, sfx: usize, layout: &ResourceLayout) -> Result<Self, PatchFxrError> {
+        let container = memory.read_pointer(sfx + layout.scene_ctrl)
+            .and_then(|scene_ctrl| memory.read_pointer(scene_ctrl + SCENE_CTRL_RESOURCE_MANAGER))
+            .and_then(|manager| memory.read_pointer(manager + RESOURCE_MANAGER_CONTAINER))
+            .ok_or(PatchFxrError::ResourceListMissing)?;
+
+        Ok(Self {
+            memory,
+            container,
+            head: memory.read_pointer(container + CONTAINER_LIST_HEAD),
+        })
+    }
+
+    pub fn memory(&self) -> &'a M {
+        self.memory
+    }
+
+    pub fn has_head(&self) -> bool {
+        self.head.is_some()
+    }
+
+    /// Addresses of the nodes in list order, sentinel excluded. The walk
+    /// stops at the sentinel or a null link. A list that loops back on
+    /// itself without passing the sentinel is refused rather than walked
+    /// forever.
+    fn nodes(&self) -> Result<Vec<usize>, PatchFxrError> {
+        let Some(head) = self.head else {
+            return Ok(Vec::new());
+        };
+
+        let mut visited = HashSet::from([head]);
+        let mut nodes = Vec::new();
+        let mut current = head;
+
+        while let Some(next) = self.memory.read_pointer(current + NODE_NEXT) {
+            if next == head || self.memory.read_u32(next + NODE_ID).unwrap_or(0) == 0 {
+                break;
+            }
+
+            if !visited.insert(next) {
+                return Err(PatchFxrError::ResourceListCycle(next));
+            }
+
+            nodes.push(next);
+            current = next;
+        }
+
+        Ok(nodes)
+    }
+
+    pub fn resident_fxrs(&self) -> Result<Vec<ResidentFxr>, PatchFxrError> {
+        Ok(self.nodes()?
+            .into_iter()
+            .map(|node| ResidentFxr {
+                id: self.memory.read_u32(node + NODE_ID).unwrap_or(0),
+                definition: self.memory.read_pointer(node + NODE_WRAPPER)
+                    .and_then(|wrapper| self.memory.read_usize(wrapper + WRAPPER_DEFINITION))
+                    .unwrap_or(0),
+            })
+            .collect())
+    }
+
+    /// Address of the wrapper's pointer to the definition of a loaded FXR.
+    pub fn definition_slot(&self, fxr_id: u32) -> Result<Option<usize>, PatchFxrError> {
+        Ok(self.nodes()?
+            .into_iter()
+            .find(|node| self.memory.read_u32(node + NODE_ID) == Some(fxr_id))
+            .and_then(|node| self.memory.read_pointer(node + NODE_WRAPPER))
+            .map(|wrapper| wrapper + WRAPPER_DEFINITION))
+    }
+
+    /// Adds an FXR the game doesn't have loaded, with a node and wrapper
+    /// allocated from the game's allocator so the game can dispose of them
+    /// like its own. Nothing is left allocated if the FXR couldn't be linked
+    /// in.
+    pub fn insert<A: Allocator + ?Sized>(&self, allocator: &A, fxr_id: u32, definition: usize) -> Option<LinkedFxr> {
+        if !self.has_head() {
+            return None;
+        }
+
+        let wrapper = allocator.allocate(WRAPPER_SIZE, 0x8);
+        let node = allocator.allocate(NODE_SIZE, 0x8);
+
+        if wrapper == 0 || node == 0 || !self.link(node, wrapper, fxr_id, definition) {
+            [wrapper, node].into_iter()
+                .filter(|a| *a != 0)
+                .for_each(|a| allocator.free(a));
+
+            return None;
+        }
+
+        Some(LinkedFxr {
+            node: Allocation { address: node, size: NODE_SIZE },
+            wrapper: Allocation { address: wrapper, size: WRAPPER_SIZE },
+        })
+    }
+
+    /// Fills in the node and wrapper, which have to be at least `NODE_SIZE`
+    /// and `WRAPPER_SIZE` bytes, and links the node in at the tail. The
+    /// game's nodes are only written once ours are filled in.
+    fn link(&self, node: usize, wrapper: usize, fxr_id: u32, definition: usize) -> bool {
+        let Some(head) = self.head else {
+            return false;
+        };
+
+        // The head is a sentinel so the tail sits right before it
+        let Some(tail) = self.memory.read_pointer(head + NODE_PREV) else {
+            return false;
+        };
+
+        let count = self.memory.read_usize(self.container + CONTAINER_RESOURCE_COUNT).unwrap_or(0);
+
+        self.memory.write(wrapper, &[0; WRAPPER_SIZE])
+            && self.memory.write_usize(wrapper + WRAPPER_DEFINITION, definition)
+            && self.memory.write(node, &[0; NODE_SIZE])
+            && self.memory.write_usize(node + NODE_NEXT, head)
+            && self.memory.write_usize(node + NODE_PREV, tail)
+            && self.memory.write_u32(node + NODE_ID, fxr_id)
+            && self.memory.write_usize(node + NODE_WRAPPER, wrapper)
+            && self.memory.write_usize(tail + NODE_NEXT, node)
+            && self.memory.write_usize(head + NODE_PREV, node)
+            && self.memory.write_usize(self.container + CONTAINER_RESOURCE_COUNT, count + 1)
+    }
+
+    /// Takes a node we linked in with `insert` back out of the list. Nodes
+    /// that are no longer in the list, because the game cleared it in the
+    /// meantime, are left alone.
+    pub fn unlink(&self, node: usize) -> Result<bool, PatchFxrError> {
+        if !self.nodes()?.contains(&node) {
+            return Ok(false);
+        }
+
+        let (Some(next), Some(prev)) = (
+            self.memory.read_pointer(node + NODE_NEXT),
+            self.memory.read_pointer(node + NODE_PREV),
+        ) else {
+            return Ok(false);
+        };
+
+        let count = self.memory.read_usize(self.container + CONTAINER_RESOURCE_COUNT).unwrap_or(1);
+
+        Ok(self.memory.write_usize(prev + NODE_NEXT, next)
+            && self.memory.write_usize(next + NODE_PREV, prev)
+            && self.memory.write_usize(self.container + CONTAINER_RESOURCE_COUNT, count.saturating_sub(1)))
+    }
+}
+
+/// The node and wrapper allocated for an FXR we linked into the resource
+/// list ourselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkedFxr {
+    pub node: Allocation,
+    pub wrapper: Allocation,
+}
+
+/// An FXR in the game's resource list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidentFxr {
+    pub id: u32,
+    /// The definition the FXR's wrapper points at.
+    pub definition: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::{FakeSfx, Heap, LAYOUT};
+
+    fn ids(sfx: &FakeSfx) -> Vec<u32> {
+        sfx.list().resident_fxrs().unwrap().iter().map(|f| f.id).collect()
+    }
+
+    #[test]
+    fn lists_fxrs_in_list_order() {
+        let sfx = FakeSfx::new(&[300, 100, 200]);
+
+        let resident = sfx.list().resident_fxrs().unwrap();
+        assert_eq!(ids(&sfx), [300, 100, 200]);
+        assert!(resident.iter().all(|f| sfx.definition(f.id) == Some(f.definition)));
+    }
+
+    #[test]
+    fn finds_the_definition_slot_in_the_wrapper() {
+        let sfx = FakeSfx::new(&[300, 100]);
+        let list = sfx.list();
+
+        let slot = list.definition_slot(100).unwrap().unwrap();
+        let wrapper = sfx.heap.read_pointer(sfx.nodes[1] + NODE_WRAPPER).unwrap();
+        assert_eq!(slot, wrapper + WRAPPER_DEFINITION);
+        assert_eq!(list.definition_slot(400).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_pointers_are_reported() {
+        let heap = Heap::default();
+        let sfx = heap.allocate(LAYOUT.scene_ctrl + 0x8, 0x8);
+
+        let result = ResourceList::locate(&heap, sfx, &LAYOUT);
+        assert!(matches!(result, Err(PatchFxrError::ResourceListMissing)));
+    }
+
+    #[test]
+    fn list_without_a_head_is_empty() {
+        let sfx = FakeSfx::new(&[]);
+        sfx.heap.write_usize(sfx.container + CONTAINER_LIST_HEAD, 0);
+        let list = sfx.list();
+
+        assert!(!list.has_head());
+        assert!(list.resident_fxrs().unwrap().is_empty());
+        assert_eq!(list.insert(&sfx.heap, 400, 0x10), None);
+    }
+
+    #[test]
+    fn lists_that_loop_without_the_sentinel_are_refused() {
+        let sfx = FakeSfx::new(&[1, 2, 3]);
+        sfx.heap.write_usize(sfx.nodes[2] + NODE_NEXT, sfx.nodes[1]);
+
+        let result = sfx.list().resident_fxrs();
+        assert!(matches!(result, Err(PatchFxrError::ResourceListCycle(node)) if node == sfx.nodes[1]));
+    }
+
+    #[test]
+    fn inserts_at_the_tail() {
+        let sfx = FakeSfx::new(&[300, 100]);
+        let definition = sfx.heap.allocate(0x10, 0x10);
+
+        assert!(sfx.list().insert(&sfx.heap, 400, definition).is_some());
+        assert_eq!(ids(&sfx), [300, 100, 400]);
+        assert_eq!(sfx.definition(400), Some(definition));
+        assert_eq!(sfx.resource_count(), 3);
+
+        // Walking backwards from the sentinel goes through the new node too
+        let tail = sfx.heap.read_pointer(sfx.head + NODE_PREV).unwrap();
+        assert_eq!(sfx.heap.read_u32(tail + NODE_ID), Some(400));
+        assert_eq!(sfx.heap.read_pointer(tail + NODE_PREV), Some(sfx.nodes[1]));
+    }
+
+    #[test]
+    fn inserting_frees_what_it_allocated_when_the_allocator_runs_dry() {
+        let sfx = FakeSfx::new(&[300]);
+
+        // The wrapper gets allocated, the node doesn't
+        sfx.heap.run_dry_after(1);
+        assert_eq!(sfx.list().insert(&sfx.heap, 400, 0x10), None);
+
+        assert_eq!(sfx.heap.freed().len(), 1);
+        assert_eq!(ids(&sfx), [300]);
+        assert_eq!(sfx.resource_count(), 1);
+    }
+
+    #[test]
+    fn inserting_frees_what_it_allocated_when_linking_fails() {
+        let sfx = FakeSfx::new(&[300]);
+
+        // A tail that can't be read
+        sfx.heap.write_usize(sfx.head + NODE_PREV, 0);
+        assert_eq!(sfx.list().insert(&sfx.heap, 400, 0x10), None);
+
+        assert_eq!(sfx.heap.freed().len(), 2);
+    }
+
+    #[test]
+    fn unlinking_takes_an_inserted_fxr_back_out() {
+        let sfx = FakeSfx::new(&[300, 100]);
+        let linked = sfx.list().insert(&sfx.heap, 400, 0x10).unwrap();
+
+        assert!(sfx.list().unlink(linked.node.address).unwrap());
+        assert_eq!(ids(&sfx), [300, 100]);
+        assert_eq!(sfx.resource_count(), 2);
+        assert_eq!(sfx.heap.read_pointer(sfx.head + NODE_PREV), Some(sfx.nodes[1]));
+
+        // It's not in the list anymore, nothing to do
+        assert!(!sfx.list().unlink(linked.node.address).unwrap());
+        assert_eq!(sfx.resource_count(), 2);
+    }
+}
@@ -0,0 +1,397 @@
+use std::mem;
+use std::sync;
+use std::time;
+use std::collections;
+
+use crate::allocation::{Allocation, AllocationTracker, Origin};
+use crate::memory::{Allocator, Memory};
+use crate::resources::ResourceList;
+
+/// A change to the definition an FXR wrapper points at.
+#[derive(Debug)]
+pub struct PendingSwap {
+    pub fxr_id: u32,
+    pub kind: SwapKind,
+}
+
+#[derive(Debug)]
+pub enum SwapKind {
+    /// Swap in a fully prepared definition.
+    Replace(Allocation),
+    /// Put back the definition the game had loaded before we touched it.
+    Restore,
+    /// Add a prepared definition under an ID the game doesn't have loaded.
+    Insert(Allocation),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapState {
+    /// Waiting for the game to reach the next safe point.
+    Queued,
+    /// The wrapper points at the new definition.
+    Applied,
+    /// The swap could not be applied, usually because the FXR got unloaded in
+    /// the meantime.
+    Failed,
+}
+
+/// FIFO of pending swaps. Pushing hands out a ticket that resolves once the
+/// item has been drained. Knows nothing about the game, draining is done by
+/// whoever calls `drain` with the function that actually applies the item.
+pub struct SwapQueue<T> {
+    pending: sync::Mutex<collections::VecDeque<(T, sync::Arc<SwapTicket>)>>,
+}
+
+impl<T> Default for SwapQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SwapQueue<T> {
+    pub const fn new() -> Self {
+        Self {
+            pending: sync::Mutex::new(collections::VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, item: T) -> sync::Arc<SwapTicket> {
+        let ticket = sync::Arc::new(SwapTicket::default());
+        self.pending.lock().unwrap().push_back((item, ticket.clone()));
+        ticket
+    }
+
+    /// Hands every queued item to `apply` in the order they were pushed and
+    /// resolves their tickets with the result.
+    pub fn drain(&self, mut apply: impl FnMut(T) -> bool) {
+        // Take the items out before applying them so the RPC thread can keep
+        // pushing while we're calling into the game.
+        let drained = mem::take(&mut *self.pending.lock().unwrap());
+
+        for (item, ticket) in drained {
+            ticket.resolve(match apply(item) {
+                true => SwapState::Applied,
+                false => SwapState::Failed,
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SwapTicket {
+    state: sync::Mutex<SwapState>,
+    resolved: sync::Condvar,
+}
+
+impl Default for SwapTicket {
+    fn default() -> Self {
+        Self {
+            state: sync::Mutex::new(SwapState::Queued),
+            resolved: sync::Condvar::new(),
+        }
+    }
+}
+
+impl SwapTicket {
+    fn resolve(&self, state: SwapState) {
+        *self.state.lock().unwrap() = state;
+        self.resolved.notify_all();
+    }
+
+    /// Blocks until the swap has been drained or the timeout passes. A swap
+    /// that timed out is still `Queued` and will be applied whenever the game
+    /// gets around to it.
+    pub fn wait(&self, timeout: time::Duration) -> SwapState {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self.resolved
+            .wait_timeout_while(state, timeout, |s| *s == SwapState::Queued)
+            .unwrap();
+
+        *state
+    }
+}
+
+impl PendingSwap {
+    /// Gives up on the swap without touching the game, for when the resource
+    /// list can't be reached. The definition it would've swapped in is
+    /// retired.
+    pub fn discard(self, allocations: &mut AllocationTracker) {
+        match self.kind {
+            SwapKind::Replace(allocation) | SwapKind::Insert(allocation) => allocations.retire(allocation),
+            SwapKind::Restore => {},
+        }
+    }
+}
+
+/// Points the FXR's wrapper at the swap's definition, or links in a new FXR,
+/// and records what happened in `allocations`. Returns whether the swap went
+/// through. Definitions that end up unreferenced are retired rather than
+/// freed.
+pub fn apply_swap<M: Memory + ?Sized, A: Allocator + ?Sized>(
+    resources: &ResourceList<'_, M>,
+    allocator: &A,
+    allocations: &mut AllocationTracker,
+    pending: PendingSwap,
+) -> bool {
+    let memory = resources.memory();
+    let definition = resources.definition_slot(pending.fxr_id)
+        .unwrap_or(None)
+        .and_then(|slot| Some((slot, memory.read_usize(slot)?)));
+
+    match (pending.kind, definition) {
+        (SwapKind::Replace(allocation), Some((slot, current))) => {
+            if !memory.write_usize(slot, allocation.address) {
+                allocations.retire(allocation);
+                return false;
+            }
+
+            allocations.replaced(pending.fxr_id, current, allocation);
+            true
+        },
+        (SwapKind::Restore, Some((slot, current))) => match allocations.restored(pending.fxr_id, current) {
+            Some(Origin::Loaded(original)) => memory.write_usize(slot, original),
+            Some(Origin::Inserted(linked)) => resources.unlink(linked.node.address).unwrap_or(false),
+            None => true,
+        },
+        (SwapKind::Insert(allocation), None) => match resources.insert(allocator, pending.fxr_id, allocation.address) {
+            Some(linked) => {
+                allocations.inserted(pending.fxr_id, linked, allocation);
+                true
+            },
+            None => {
+                allocations.retire(allocation);
+                false
+            },
+        },
+        // Another insert for the same ID beat us to it
+        (SwapKind::Insert(allocation), Some(_)) => {
+            allocations.retire(allocation);
+
+            false
+        },
+        // The FXR got unloaded, nothing will ever reference the new definition
+        (SwapKind::Replace(allocation), None) => {
+            allocations.retire(allocation);
+            allocations.forget(pending.fxr_id);
+
+            false
+        },
+        (SwapKind::Restore, None) => {
+            allocations.forget(pending.fxr_id);
+
+            false
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::heap::{FakeSfx, DEFINITION_SIZE};
+
+    const NO_WAIT: time::Duration = time::Duration::ZERO;
+
+    #[test]
+    fn drains_in_the_order_items_were_pushed() {
+        let queue = SwapQueue::new();
+        (0..5).for_each(|i| { queue.push(i); });
+
+        let mut drained = Vec::new();
+        queue.drain(|i| { drained.push(i); true });
+        assert_eq!(drained, [0, 1, 2, 3, 4]);
+
+        // Nothing is applied twice
+        queue.drain(|_| panic!("queue should be empty"));
+    }
+
+    #[test]
+    fn tickets_resolve_with_the_result() {
+        let queue = SwapQueue::new();
+        let applied = queue.push(true);
+        let failed = queue.push(false);
+
+        queue.drain(|succeeds| succeeds);
+        assert_eq!(applied.wait(NO_WAIT), SwapState::Applied);
+        assert_eq!(failed.wait(NO_WAIT), SwapState::Failed);
+    }
+
+    #[test]
+    fn waiting_wakes_up_when_drained_from_another_thread() {
+        let queue = sync::Arc::new(SwapQueue::new());
+        let ticket = queue.push(());
+
+        let drainer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                thread::sleep(time::Duration::from_millis(20));
+                queue.drain(|_| true);
+            })
+        };
+
+        assert_eq!(ticket.wait(time::Duration::from_secs(10)), SwapState::Applied);
+        drainer.join().unwrap();
+    }
+
+    #[test]
+    fn timing_out_leaves_the_ticket_queued() {
+        let queue = SwapQueue::new();
+        let ticket = queue.push(());
+
+        assert_eq!(ticket.wait(time::Duration::from_millis(10)), SwapState::Queued);
+
+        // It still goes through whenever the queue gets drained
+        queue.drain(|_| true);
+        assert_eq!(ticket.wait(NO_WAIT), SwapState::Applied);
+    }
+
+    #[test]
+    fn items_pushed_during_a_drain_wait_for_the_next_one() {
+        let queue = SwapQueue::new();
+        queue.push(0);
+
+        let mut late = None;
+        let mut drained = Vec::new();
+        queue.drain(|i| {
+            late.get_or_insert_with(|| queue.push(1));
+            drained.push(i);
+            true
+        });
+
+        let late = late.unwrap();
+        assert_eq!(drained, [0]);
+        assert_eq!(late.wait(NO_WAIT), SwapState::Queued);
+
+        queue.drain(|i| { drained.push(i); true });
+        assert_eq!(drained, [0, 1]);
+        assert_eq!(late.wait(NO_WAIT), SwapState::Applied);
+    }
+
+    fn prepare(sfx: &FakeSfx) -> Allocation {
+        Allocation {
+            address: sfx.heap.allocate(DEFINITION_SIZE, 0x10),
+            size: DEFINITION_SIZE,
+        }
+    }
+
+    fn swap(sfx: &FakeSfx, allocations: &mut AllocationTracker, fxr_id: u32, kind: SwapKind) -> bool {
+        apply_swap(&sfx.list(), &sfx.heap, allocations, PendingSwap { fxr_id, kind })
+    }
+
+    #[test]
+    fn patching_points_the_wrapper_at_our_definition() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let ours = prepare(&sfx);
+
+        assert!(swap(&sfx, &mut allocations, 300, SwapKind::Replace(ours)));
+        assert_eq!(sfx.definition(300), Some(ours.address));
+        assert_eq!(allocations.current(300), Some(ours.address));
+        assert!(allocations.take_retired().is_empty());
+    }
+
+    #[test]
+    fn patching_again_retires_our_previous_definition() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let first = prepare(&sfx);
+        let second = prepare(&sfx);
+
+        swap(&sfx, &mut allocations, 300, SwapKind::Replace(first));
+        assert!(swap(&sfx, &mut allocations, 300, SwapKind::Replace(second)));
+
+        assert_eq!(sfx.definition(300), Some(second.address));
+        assert_eq!(allocations.take_retired(), [first]);
+    }
+
+    #[test]
+    fn restoring_puts_back_the_original() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let original = sfx.definition(300).unwrap();
+        let ours = prepare(&sfx);
+
+        swap(&sfx, &mut allocations, 300, SwapKind::Replace(ours));
+        assert!(swap(&sfx, &mut allocations, 300, SwapKind::Restore));
+
+        assert_eq!(sfx.definition(300), Some(original));
+        assert!(!allocations.is_tracked(300));
+        assert_eq!(allocations.take_retired(), [ours]);
+    }
+
+    #[test]
+    fn restoring_leaves_fxrs_the_game_reloaded_alone() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+
+        swap(&sfx, &mut allocations, 300, SwapKind::Replace(prepare(&sfx)));
+        let reloaded = sfx.reload(300);
+        assert!(swap(&sfx, &mut allocations, 300, SwapKind::Restore));
+
+        // Ours went with the game's old wrapper, nothing to put back or free
+        assert_eq!(sfx.definition(300), Some(reloaded));
+        assert!(!allocations.is_tracked(300));
+        assert!(allocations.take_retired().is_empty());
+    }
+
+    #[test]
+    fn patching_an_unloaded_fxr_fails() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let ours = prepare(&sfx);
+
+        assert!(!swap(&sfx, &mut allocations, 400, SwapKind::Replace(ours)));
+        assert_eq!(sfx.definition(300).map(|d| d == ours.address), Some(false));
+        assert_eq!(allocations.take_retired(), [ours]);
+    }
+
+    #[test]
+    fn inserting_links_in_a_new_fxr() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let ours = prepare(&sfx);
+
+        assert!(swap(&sfx, &mut allocations, 400, SwapKind::Insert(ours)));
+        assert_eq!(sfx.definition(400), Some(ours.address));
+        assert_eq!(sfx.resource_count(), 2);
+
+        // Losing the race to another insert for the same ID
+        let late = prepare(&sfx);
+        assert!(!swap(&sfx, &mut allocations, 400, SwapKind::Insert(late)));
+        assert_eq!(sfx.definition(400), Some(ours.address));
+        assert_eq!(allocations.take_retired(), [late]);
+    }
+
+    #[test]
+    fn restoring_an_insert_unlinks_it() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let ours = prepare(&sfx);
+
+        swap(&sfx, &mut allocations, 400, SwapKind::Insert(ours));
+        assert!(allocations.is_tracked(400));
+        assert_eq!(allocations.stats().live_bytes, DEFINITION_SIZE);
+
+        assert!(swap(&sfx, &mut allocations, 400, SwapKind::Restore));
+        assert_eq!(sfx.definition(400), None);
+        assert_eq!(sfx.resource_count(), 1);
+
+        // The definition goes along with the node and wrapper we allocated
+        let retired = allocations.take_retired();
+        assert_eq!(retired.len(), 3);
+        assert_eq!(retired[0], ours);
+    }
+
+    #[test]
+    fn failed_inserts_leave_nothing_allocated() {
+        let sfx = FakeSfx::new(&[300]);
+        let mut allocations = AllocationTracker::new();
+        let ours = prepare(&sfx);
+
+        sfx.heap.run_dry_after(0);
+        assert!(!swap(&sfx, &mut allocations, 400, SwapKind::Insert(ours)));
+        assert!(!allocations.is_tracked(400));
+        assert_eq!(allocations.take_retired(), [ours]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::ops::Range;
+
+use crate::{
+    find_singletons, resolve_singletons, ImageView, PatternError, ResolvedSingleton,
+    SingletonCandidate,
+};
+
+/// Room each null check gets in the synthetic `.text`. The check is followed
+/// by the class name function of the singleton's DLRuntimeClass.
+pub(crate) const CHECK_STRIDE: usize = 0x40;
+const CLASS_NAME_OFFSET: usize = 0x30;
+
+/// Builds `.text` and `.data` sections laid out the way the games lay out
+/// their singleton null checks, so the scan can be checked against known
+/// singletons without a game executable.
+#[derive(Debug, Clone)]
+pub(crate) struct Fixture {
+    text_start: usize,
+    data_start: usize,
+    singletons: Vec<String>,
+    decoys: usize,
+}
+
+/// A singleton the fixture put in its sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FixtureSingleton {
+    pub(crate) name: String,
+    pub(crate) candidate: SingletonCandidate,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyntheticImage {
+    pub(crate) text_start: usize,
+    pub(crate) text: Vec<u8>,
+    pub(crate) data_start: usize,
+    pub(crate) data: Vec<u8>,
+    /// What the scan is expected to find, in `.text` order.
+    pub(crate) singletons: Vec<FixtureSingleton>,
+}
+
+impl Fixture {
+    pub(crate) fn new(text_start: usize, data_start: usize) -> Self {
+        Self {
+            text_start,
+            data_start,
+            singletons: Vec::new(),
+            decoys: 0,
+        }
+    }
+
+    pub(crate) fn singleton(mut self, name: &str) -> Self {
+        self.singletons.push(name.to_string());
+        self
+    }
+
+    /// Adds a null check whose static is outside of `.data`, which the scan
+    /// has to throw out.
+    pub(crate) fn decoy(mut self) -> Self {
+        self.decoys += 1;
+        self
+    }
+
+    pub(crate) fn build(&self) -> SyntheticImage {
+        // The name getter comes first so the calls to it jump backwards
+        let name_getter = self.text_start;
+        let mut text = vec![0xcc; CHECK_STRIDE];
+        text[0] = 0xc3;
+
+        // Each singleton gets its static, its DLRuntimeClass, the vtable of
+        // the latter and its name in .data
+        let mut data = Vec::new();
+        let mut singletons = Vec::new();
+        for name in &self.singletons {
+            let static_address = self.data_start + reserve(&mut data, 0x8);
+            let metadata_address = self.data_start + reserve(&mut data, 0x10);
+            let vtable = self.data_start + reserve(&mut data, 0x18);
+            let name_address = self.data_start + reserve(&mut data, name.len() + 1);
+
+            let class_name = self.text_start + text.len() + CLASS_NAME_OFFSET;
+            let candidate = SingletonCandidate { static_address, metadata_address, name_getter };
+            push_null_check(&mut text, self.text_start, &candidate);
+
+            // LEA RAX, [name]; RET
+            let at = class_name - self.text_start;
+            text[at..at + 3].copy_from_slice(&[0x48, 0x8d, 0x05]);
+            text[at + 3..at + 7].copy_from_slice(&relative(name_address, class_name + 7));
+            text[at + 7] = 0xc3;
+
+            let offset = |address: usize| address - self.data_start;
+            put_usize(&mut data, offset(metadata_address), vtable);
+            put_usize(&mut data, offset(vtable) + 0x8, class_name);
+            data[offset(name_address)..offset(name_address) + name.len()].copy_from_slice(name.as_bytes());
+
+            singletons.push(FixtureSingleton { name: name.clone(), candidate });
+        }
+
+        let outside_data = self.data_start + data.len() + 0x1000;
+        for _ in 0..self.decoys {
+            push_null_check(&mut text, self.text_start, &SingletonCandidate {
+                static_address: outside_data,
+                metadata_address: outside_data,
+                name_getter,
+            });
+        }
+
+        SyntheticImage {
+            text_start: self.text_start,
+            text,
+            data_start: self.data_start,
+            data,
+            singletons,
+        }
+    }
+}
+
+impl SyntheticImage {
+    pub(crate) fn data_range(&self) -> Range<usize> {
+        self.data_start..self.data_start + self.data.len()
+    }
+
+    /// Runs the scan over the synthetic sections.
+    pub(crate) fn scan(&self) -> Result<Vec<SingletonCandidate>, PatternError> {
+        find_singletons(&self.text, self.text_start, &self.data_range())
+    }
+
+    /// Runs the scan and names the singletons from their metadata.
+    pub(crate) fn resolve(&self) -> Result<Vec<ResolvedSingleton>, PatternError> {
+        resolve_singletons(self, &self.text, self.text_start, &self.data_range())
+    }
+}
+
+impl ImageView for SyntheticImage {
+    fn bytes(&self, address: usize, len: usize) -> Option<&[u8]> {
+        let (start, section) = match address >= self.data_start {
+            true => (self.data_start, &self.data),
+            false => (self.text_start, &self.text),
+        };
+
+        section.get(address.checked_sub(start)?..)?.get(..len)
+    }
+}
+
+/// Makes room for `size` bytes at the end of `section`, 0x10 aligned, and
+/// yields where they start.
+fn reserve(section: &mut Vec<u8>, size: usize) -> usize {
+    let start = section.len();
+    section.resize((start + size).next_multiple_of(0x10), 0);
+    start
+}
+
+fn put_usize(section: &mut [u8], offset: usize, value: usize) {
+    section[offset..offset + 8].copy_from_slice(&(value as u64).to_le_bytes());
+}
+
+/// Displacement from the end of an instruction to `target`.
+fn relative(target: usize, end: usize) -> [u8; 4] {
+    (target.wrapping_sub(end) as i32).to_le_bytes()
+}
+
+/// Appends a null check referring to the candidate's addresses, encoded the
+/// way the compiler emits them.
+fn push_null_check(text: &mut Vec<u8>, text_start: usize, candidate: &SingletonCandidate) {
+    let base = text_start + text.len();
+
+    let mut check = vec![0xcc; CHECK_STRIDE];
+    // MOV RAX, [static]
+    check[0..3].copy_from_slice(&[0x48, 0x8b, 0x05]);
+    check[3..7].copy_from_slice(&relative(candidate.static_address, base + 7));
+    // TEST RAX, RAX
+    check[7..10].copy_from_slice(&[0x48, 0x85, 0xc0]);
+    // JNZ +2e
+    check[10..12].copy_from_slice(&[0x75, 0x2e]);
+    // LEA RCX, [metadata]
+    check[12..15].copy_from_slice(&[0x48, 0x8d, 0x0d]);
+    check[15..19].copy_from_slice(&relative(candidate.metadata_address, base + 19));
+    // CALL get_singleton_name
+    check[19] = 0xe8;
+    check[20..24].copy_from_slice(&relative(candidate.name_getter, base + 24));
+
+    text.extend_from_slice(&check);
+}
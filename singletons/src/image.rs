@@ -0,0 +1,51 @@
+use std::ops::Range;
+
+use pelite::pe64::{Pe, PeFile};
+
+use crate::{resolve_singletons, ImageError, ImageView, ResolvedSingleton};
+
+/// A 64-bit executable read from disk. Addresses are virtual addresses
+/// against the image base the executable asks for.
+pub struct PeImage<'a> {
+    file: PeFile<'a>,
+}
+
+impl<'a> PeImage<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ImageError> {
+        Ok(Self {
+            file: PeFile::from_bytes(bytes)?,
+        })
+    }
+
+    /// Where the section ends up once the image is loaded, along with the
+    /// bytes the file has for it. Zero-initialized data at the end of a
+    /// section isn't part of the latter.
+    pub fn section(&self, name: &str) -> Result<(Range<usize>, &'a [u8]), ImageError> {
+        let header = self.file.section_headers()
+            .by_name(name)
+            .ok_or_else(|| ImageError::MissingSection(name.to_string()))?;
+
+        let base = self.file.optional_header().ImageBase as usize;
+        let range = header.virtual_range();
+
+        Ok((
+            base + range.start as usize..base + range.end as usize,
+            self.file.get_section_bytes(header)?,
+        ))
+    }
+
+    /// Finds and names the singletons the same way the agent does in the
+    /// game, reading the names from the image instead of asking the game.
+    pub fn singletons(&self) -> Result<Vec<ResolvedSingleton>, ImageError> {
+        let (text_range, text) = self.section(".text")?;
+        let (data_range, _) = self.section(".data")?;
+
+        Ok(resolve_singletons(self, text, text_range.start, &data_range)?)
+    }
+}
+
+impl ImageView for PeImage<'_> {
+    fn bytes(&self, address: usize, len: usize) -> Option<&[u8]> {
+        self.file.read_bytes(address as u64).ok()?.get(..len)
+    }
+}
@@ -0,0 +1,33 @@
+use thiserror::Error;
+
+#[cfg(test)]
+mod fixture;
+mod image;
+mod pattern;
+mod resolve;
+mod scan;
+
+pub use image::PeImage;
+pub use pattern::{BitPattern, PatternMatch};
+pub use resolve::{resolve_name, resolve_singletons, ImageView, ResolvedSingleton};
+pub use scan::{find_singletons, SingletonCandidate, NULL_CHECK_PATTERN};
+
+#[derive(Debug, Error)]
+pub enum PatternError {
+    #[error("Unexpected character {0:?} in pattern.")]
+    Character(char),
+    #[error("Pattern ends halfway through a byte.")]
+    PartialByte,
+    #[error("Pattern has unbalanced capture brackets.")]
+    UnbalancedCapture,
+}
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("Could not read the executable. {0}")]
+    Parse(#[from] pelite::Error),
+    #[error("Executable has no {0} section.")]
+    MissingSection(String),
+    #[error("{0}")]
+    Pattern(#[from] PatternError),
+}
@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+use crate::PatternError;
+
+/// A byte pattern written out bit by bit, with `.` for bits that can be
+/// anything. Bytes in square brackets are captured. Same notation as the
+/// instruction patterns the agent matches.
+#[derive(Debug, Clone)]
+pub struct BitPattern {
+    /// Mask and value per byte.
+    bytes: Vec<(u8, u8)>,
+    captures: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternMatch {
+    /// Offset of the match in the scanned bytes.
+    pub location: usize,
+    /// Offsets of the captured bytes in the scanned bytes.
+    pub captures: Vec<Range<usize>>,
+}
+
+impl BitPattern {
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        let mut bytes = Vec::new();
+        let mut captures = Vec::new();
+        let mut capture_start = None;
+        let (mut mask, mut value, mut bits) = (0u8, 0u8, 0);
+
+        for c in pattern.chars() {
+            match c {
+                '0' | '1' | '.' => {
+                    mask = mask << 1 | (c != '.') as u8;
+                    value = value << 1 | (c == '1') as u8;
+                    bits += 1;
+
+                    if bits == 8 {
+                        bytes.push((mask, value));
+                        (mask, value, bits) = (0, 0, 0);
+                    }
+                },
+                '[' if bits == 0 && capture_start.is_none() => capture_start = Some(bytes.len()),
+                ']' if bits == 0 => {
+                    let start = capture_start.take().ok_or(PatternError::UnbalancedCapture)?;
+                    captures.push(start..bytes.len());
+                },
+                '[' | ']' => return Err(PatternError::UnbalancedCapture),
+                c if c.is_whitespace() => {},
+                c => return Err(PatternError::Character(c)),
+            }
+        }
+
+        if bits != 0 {
+            return Err(PatternError::PartialByte);
+        }
+
+        if capture_start.is_some() {
+            return Err(PatternError::UnbalancedCapture);
+        }
+
+        Ok(Self { bytes, captures })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.bytes.len()
+            && self.bytes.iter().zip(bytes).all(|((mask, value), byte)| byte & mask == *value)
+    }
+
+    /// Every match in `haystack`, overlapping ones included.
+    pub fn scan<'a>(&'a self, haystack: &'a [u8]) -> impl Iterator<Item = PatternMatch> + 'a {
+        let end = (haystack.len() + 1).saturating_sub(self.bytes.len().max(1));
+
+        (0..end)
+            .filter(|location| self.matches(&haystack[*location..]))
+            .map(|location| PatternMatch {
+                location,
+                captures: self.captures.iter()
+                    .map(|c| location + c.start..location + c.end)
+                    .collect(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masked_bits_match_anything() {
+        let pattern = BitPattern::parse("0100.... 1000101.").unwrap();
+
+        assert_eq!(pattern.len(), 2);
+        assert!(pattern.matches(&[0x48, 0x8b]));
+        assert!(pattern.matches(&[0x4f, 0x8a, 0xff]));
+        assert!(!pattern.matches(&[0x58, 0x8b]));
+        assert!(!pattern.matches(&[0x48]));
+    }
+
+    #[test]
+    fn captures_are_reported_per_match() {
+        let pattern = BitPattern::parse("11101000 [........ ........]").unwrap();
+        let matches = pattern.scan(&[0x00, 0xe8, 0x01, 0xe8, 0x03]).collect::<Vec<_>>();
+
+        // Overlapping matches count, one starting too close to the end doesn't
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].location, 1);
+        assert_eq!(matches[0].captures, vec![Range { start: 2, end: 4 }]);
+        assert_eq!(pattern.scan(&[0xe8, 0xe8, 0x00, 0x00]).count(), 2);
+    }
+
+    #[test]
+    fn malformed_patterns_are_refused() {
+        assert!(matches!(BitPattern::parse("0101 x"), Err(PatternError::Character('x'))));
+        assert!(matches!(BitPattern::parse("01010101 0101"), Err(PatternError::PartialByte)));
+        assert!(matches!(BitPattern::parse("[01010101"), Err(PatternError::UnbalancedCapture)));
+        assert!(matches!(BitPattern::parse("01010101]"), Err(PatternError::UnbalancedCapture)));
+        assert!(matches!(BitPattern::parse("[[01010101]]"), Err(PatternError::UnbalancedCapture)));
+        // Captures can't start or end halfway through a byte
+        assert!(matches!(BitPattern::parse("0101[0101]"), Err(PatternError::UnbalancedCapture)));
+    }
+
+    #[test]
+    fn null_check_pattern_parses() {
+        let pattern = BitPattern::parse(crate::NULL_CHECK_PATTERN).unwrap();
+
+        assert_eq!(pattern.len(), 24);
+        assert!(!pattern.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use std::ops::Range;
+
+use crate::{find_singletons, PatternError, SingletonCandidate};
+
+/// Slot in DLRuntimeClass's vtable of the function returning the class name.
+const CLASS_NAME_SLOT: usize = 1;
+/// Class names are short, anything longer means we're reading garbage.
+const MAX_NAME_LENGTH: usize = 0x100;
+
+/// Read access to an executable image by virtual address.
+pub trait ImageView {
+    /// `len` bytes at `address`, if the image has that many bytes there.
+    fn bytes(&self, address: usize, len: usize) -> Option<&[u8]>;
+
+    fn read_usize(&self, address: usize) -> Option<usize> {
+        let bytes = self.bytes(address, 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?) as usize)
+    }
+
+    fn read_c_str(&self, address: usize) -> Option<String> {
+        let bytes = (0..MAX_NAME_LENGTH)
+            .map(|i| self.bytes(address + i, 1).map(|b| b[0]))
+            .take_while(|b| *b != Some(0))
+            .collect::<Option<Vec<_>>>()?;
+
+        match bytes.len() {
+            MAX_NAME_LENGTH => None,
+            _ => String::from_utf8(bytes).ok(),
+        }
+    }
+}
+
+/// A singleton found in an image, named if its metadata could be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedSingleton {
+    pub name: Option<String>,
+    pub candidate: SingletonCandidate,
+}
+
+/// Works out a singleton's name without running any of the game's code,
+/// unlike calling `name_getter`. The metadata is a DLRuntimeClass whose
+/// vtable has a function along the lines of `LEA RAX, [name]; RET`, the
+/// name is read from where that points.
+pub fn resolve_name<I: ImageView + ?Sized>(image: &I, candidate: &SingletonCandidate) -> Option<String> {
+    let vtable = image.read_usize(candidate.metadata_address)?;
+    let class_name = image.read_usize(vtable + CLASS_NAME_SLOT * 8)?;
+
+    let code = image.bytes(class_name, 8)?;
+    if code[..3] != [0x48, 0x8d, 0x05] || code[7] != 0xc3 {
+        return None;
+    }
+
+    let displacement = i32::from_le_bytes(code[3..7].try_into().unwrap()) as isize;
+    image.read_c_str((class_name + 7).checked_add_signed(displacement)?)
+}
+
+/// Finds the singletons in the image's `.text` and names them from their
+/// metadata.
+pub fn resolve_singletons<I: ImageView + ?Sized>(
+    image: &I,
+    text: &[u8],
+    text_start: usize,
+    data: &Range<usize>,
+) -> Result<Vec<ResolvedSingleton>, PatternError> {
+    Ok(find_singletons(text, text_start, data)?
+        .into_iter()
+        .map(|candidate| ResolvedSingleton {
+            name: resolve_name(image, &candidate),
+            candidate,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn names_come_from_the_runtime_class() {
+        let image = Fixture::new(0x1_4000_1000, 0x1_4300_0000)
+            .singleton("CSSfx")
+            .singleton("CSWorldGeomMan")
+            .decoy()
+            .build();
+
+        let names = image.resolve().unwrap().into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names, [Some(String::from("CSSfx")), Some(String::from("CSWorldGeomMan"))]);
+    }
+
+    #[test]
+    fn class_name_functions_have_to_look_right() {
+        let mut image = Fixture::new(0x1_4000_1000, 0x1_4300_0000).singleton("CSSfx").build();
+        let candidate = image.singletons[0].candidate;
+
+        // Point the vtable's class name slot at the name getter, a bare RET
+        let vtable = image.read_usize(candidate.metadata_address).unwrap() - image.data_start;
+        let slot = vtable + CLASS_NAME_SLOT * 8;
+        image.data[slot..slot + 8].copy_from_slice(&(image.text_start as u64).to_le_bytes());
+
+        assert_eq!(resolve_name(&image, &candidate), None);
+        assert_eq!(image.resolve().unwrap()[0].name, None);
+    }
+
+    #[test]
+    fn unterminated_names_are_refused() {
+        let mut image = Fixture::new(0x1_4000_1000, 0x1_4300_0000).singleton("CSSfx").build();
+        let candidate = image.singletons[0].candidate;
+        let data_len = image.data.len();
+        image.data.resize(data_len + MAX_NAME_LENGTH * 2, b'A');
+
+        // Overwrite the name and everything after it
+        let name = image.data.windows(5).position(|w| w == b"CSSfx").unwrap();
+        image.data[name..].fill(b'A');
+
+        assert_eq!(resolve_name(&image, &candidate), None);
+    }
+}
@@ -0,0 +1,124 @@
+use std::ops::Range;
+
+use crate::{BitPattern, PatternError};
+
+pub const NULL_CHECK_PATTERN: &str = concat!(
+    //  0 MOV REG, [MEM]
+    "01001... 10001011 00...101 [........ ........ ........ ........]",
+    //  7 TEST REG, REG
+    "01001... 10000101 11......",
+    // 10 JNZ +2e
+    "01110101 ........",
+    // 12 LEA RCX, [runtime_class_metadata]
+    "01001... 10001101 00001101 [........ ........ ........ ........]",
+    // 19 CALL get_singleton_name
+    "11101000 [........ ........ ........ ........]",
+);
+
+/// A null check on a singleton's static that names the singleton when the
+/// instance is missing. All addresses are in the image's address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SingletonCandidate {
+    /// Where the pointer to the instance lives.
+    pub static_address: usize,
+    /// The DLRuntimeClass metadata passed to the name getter.
+    pub metadata_address: usize,
+    /// `char* get_singleton_name(metadata)`.
+    pub name_getter: usize,
+}
+
+/// Looks for the singleton null checks in `text`, the bytes of the `.text`
+/// section mapped at `text_start`. Candidates are only kept if the static
+/// and the metadata are in `data` and the name getter is in `.text`, which
+/// weeds out code that happens to match the pattern.
+pub fn find_singletons(
+    text: &[u8],
+    text_start: usize,
+    data: &Range<usize>,
+) -> Result<Vec<SingletonCandidate>, PatternError> {
+    let pattern = BitPattern::parse(NULL_CHECK_PATTERN)?;
+    let text_range = text_start..text_start + text.len();
+
+    Ok(pattern.scan(text)
+        .filter_map(|candidate| {
+            let base = text_start + candidate.location;
+            let displacement = |capture: usize| {
+                let bytes = &text[candidate.captures[capture].clone()];
+                i32::from_le_bytes(bytes.try_into().unwrap()) as isize
+            };
+
+            // Displacements are relative to the end of their instruction
+            let candidate = SingletonCandidate {
+                static_address: (base + 7).checked_add_signed(displacement(0))?,
+                metadata_address: (base + 19).checked_add_signed(displacement(1))?,
+                name_getter: (base + 24).checked_add_signed(displacement(2))?,
+            };
+
+            let valid = data.contains(&candidate.static_address)
+                && data.contains(&candidate.metadata_address)
+                && text_range.contains(&candidate.name_getter);
+
+            valid.then_some(candidate)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Fixture;
+
+    const TEXT: usize = 0x1_4000_1000;
+    const DATA: usize = 0x1_4300_0000;
+
+    fn expected(image: &crate::fixture::SyntheticImage) -> Vec<SingletonCandidate> {
+        image.singletons.iter().map(|s| s.candidate).collect()
+    }
+
+    #[test]
+    fn finds_known_singletons_in_text_order() {
+        let image = Fixture::new(TEXT, DATA)
+            .singleton("CSSfx")
+            .singleton("CSWorldGeomMan")
+            .singleton("CSTaskGroup")
+            .build();
+
+        let found = image.scan().unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found, expected(&image));
+    }
+
+    #[test]
+    fn checks_on_statics_outside_of_data_are_thrown_out() {
+        let image = Fixture::new(TEXT, DATA)
+            .singleton("CSSfx")
+            .decoy()
+            .decoy()
+            .build();
+
+        // The decoys match the pattern, they just don't point into .data
+        let pattern = BitPattern::parse(NULL_CHECK_PATTERN).unwrap();
+        assert_eq!(pattern.scan(&image.text).count(), 3);
+        assert_eq!(image.scan().unwrap(), expected(&image));
+    }
+
+    #[test]
+    fn follows_negative_displacements() {
+        // .data sits below .text so every reference into it points backwards
+        let image = Fixture::new(DATA, TEXT).singleton("CSSfx").singleton("CSFD4VirtualMemoryFlag").build();
+
+        let found = image.scan().unwrap();
+        assert!(found.iter().all(|c| c.static_address < image.text_start));
+        assert_eq!(found, expected(&image));
+    }
+
+    #[test]
+    fn name_getters_have_to_be_in_text() {
+        let image = Fixture::new(TEXT, DATA).singleton("CSSfx").build();
+        let text = &image.text[crate::fixture::CHECK_STRIDE..];
+        let text_start = image.text_start + crate::fixture::CHECK_STRIDE;
+
+        // Without the name getter at the start the call leads out of .text
+        assert!(find_singletons(text, text_start, &image.data_range()).unwrap().is_empty());
+    }
+}